@@ -2,11 +2,15 @@
 
 uniform sampler2DShadow shadow_map;
 uniform vec3 light_loc;
-uniform vec3 paint;
+uniform vec3 camera_position;
 
 in float camera_distance;
 in vec4 shadow_coord;
-in vec4 model_normal;
+in vec3 model_normal;
+in vec3 world_position;
+flat in vec3 v_color;
+flat in float v_roughness;
+flat in float v_emissive;
 
 out vec4 color;
 
@@ -14,7 +18,13 @@ void main() {
     vec3 light_color = vec3(1,1,1);
     float bias = 0.00; // Geometry does not require bias
 
-    float lum = max(dot(normalize(model_normal.xyz), normalize(light_loc)), 0.0);
+    vec3 normal = normalize(model_normal);
+    float lum = max(dot(normal, normalize(light_loc)), 0.0);
+
+    // Blinn-Phong highlight, tighter and brighter the smoother the surface.
+    vec3 half_vector = normalize(normalize(light_loc) + normalize(camera_position - world_position));
+    float shininess = mix(64.0, 2.0, v_roughness);
+    float specular = (1.0 - v_roughness) * pow(max(dot(normal, half_vector), 0.0), shininess);
 
     float visibility = 1.0;
 
@@ -38,6 +48,8 @@ void main() {
 
     float fog = 1.0 - exp(-(camera_distance / 500.0));
     //    float fog = 0.0;
-    color = vec4(mix(max(lum * visibility, 0.05) * paint * light_color, vec3(0.01, 0.01, 0.01), fog), 1.0);
+    vec3 lit = max(lum * visibility, 0.05) * v_color * light_color + specular * visibility * light_color;
+    lit = mix(lit, v_color, v_emissive);
+    color = vec4(mix(lit, vec3(0.01, 0.01, 0.01), fog), 1.0);
 
 }
\ No newline at end of file
@@ -7,10 +7,17 @@ uniform mat4 depth_bias_mvp;
 in vec3 position;
 in vec3 normal;
 in mat4 model;
+in vec3 color;
+in float roughness;
+in float emissive;
 
 out float camera_distance;
 out vec4 shadow_coord;
 out vec3 model_normal;
+out vec3 world_position;
+flat out vec3 v_color;
+flat out float v_roughness;
+flat out float v_emissive;
 
 void main() {
     gl_Position =  projection * view * model * vec4(position, 1.0);
@@ -20,4 +27,9 @@ void main() {
 
     model_normal = mat3(model) * normal;
     shadow_coord = depth_bias_mvp * model * vec4(position, 1.0);
+    world_position = (model * vec4(position, 1.0)).xyz;
+
+    v_color = color;
+    v_roughness = roughness;
+    v_emissive = emissive;
 }
\ No newline at end of file
@@ -0,0 +1,58 @@
+#version 330 core
+
+uniform sampler2DShadow shadow_map;
+uniform vec3 light_loc;
+uniform vec3 camera_position;
+uniform sampler2D diffuse_texture;
+
+in float camera_distance;
+in vec4 shadow_coord;
+in vec3 model_normal;
+in vec3 world_position;
+in vec2 v_tex_coords;
+flat in vec3 v_color;
+flat in float v_roughness;
+flat in float v_emissive;
+
+out vec4 color;
+
+void main() {
+    vec3 light_color = vec3(1,1,1);
+    vec3 base_color = v_color * texture(diffuse_texture, v_tex_coords).rgb;
+    float bias = 0.00; // Geometry does not require bias
+
+    vec3 normal = normalize(model_normal);
+    float lum = max(dot(normal, normalize(light_loc)), 0.0);
+
+    // Blinn-Phong highlight, tighter and brighter the smoother the surface.
+    vec3 half_vector = normalize(normalize(light_loc) + normalize(camera_position - world_position));
+    float shininess = mix(64.0, 2.0, v_roughness);
+    float specular = (1.0 - v_roughness) * pow(max(dot(normal, half_vector), 0.0), shininess);
+
+    float visibility = 1.0;
+
+    if(shadow_coord.y > 0 && shadow_coord.y < 1.0 && shadow_coord.x > 0 && shadow_coord.x < 1.0 && shadow_coord.z < 1.0 && shadow_coord.z > 0.0) {
+        //visibility = texture(shadow_map, vec3(shadow_coord.xy, (shadow_coord.z-bias)/shadow_coord.w));
+
+        float shadow = 0.0;
+        vec2 texelSize = 1.0 / textureSize(shadow_map, 0);
+        for(int x = -1; x <= 1; ++x)
+        {
+            for(int y = -1; y <= 1; ++y)
+            {
+                vec2 bits = shadow_coord.xy + vec2(x, y) * texelSize;
+                float pcfDepth = texture(shadow_map, vec3( bits, (shadow_coord.z-bias)/shadow_coord.w));
+                shadow += shadow_coord.z - bias > pcfDepth ? 1.0 : 0.0;
+            }
+        }
+        shadow /= 9.0;
+        visibility = 1.0 - shadow;
+    }
+
+    float fog = 1.0 - exp(-(camera_distance / 500.0));
+    //    float fog = 0.0;
+    vec3 lit = max(lum * visibility, 0.05) * base_color * light_color + specular * visibility * light_color;
+    lit = mix(lit, base_color, v_emissive);
+    color = vec4(mix(lit, vec3(0.01, 0.01, 0.01), fog), 1.0);
+
+}
\ No newline at end of file
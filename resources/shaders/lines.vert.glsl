@@ -0,0 +1,13 @@
+#version 330
+in vec3 position;
+in vec3 color;
+
+out vec3 v_color;
+
+uniform mat4 view;
+uniform mat4 projection;
+
+void main() {
+    v_color = color;
+    gl_Position = projection * view * vec4(position, 1.0);
+}
@@ -1,5 +1,8 @@
 use imgui::Io;
 
+const FOLLOW_DISTANCE: f32 = 12.0;
+const FOLLOW_HEIGHT: f32 = 3.0;
+
 pub struct Camera {
     pub position: glam::Vec3,
     pub target_position: glam::Vec3,
@@ -32,11 +35,6 @@ impl Camera {
         let delta_mouse_x = io.mouse_delta[0];
         let delta_mouse_y = io.mouse_delta[1];
 
-        let w = io.keys_down[('w' as usize - 'a' as usize) + 10];
-        let a = io.keys_down[('a' as usize - 'a' as usize) + 10];
-        let s = io.keys_down[('s' as usize - 'a' as usize) + 10];
-        let d = io.keys_down[('d' as usize - 'a' as usize) + 10];
-
         let rotation = if io.mouse_down[1] {
             glam::Vec2::new(-delta_mouse_x * dt * 0.1, -delta_mouse_y * dt * 0.1)
         } else {
@@ -44,10 +42,20 @@ impl Camera {
         };
 
         let speed = if io.key_shift { 60.0 } else { 10.0 };
-        let translation_x = if a { -1.0 } else if d { 1.0 } else { 0.0 };
-        let translation_y = if w { 1.0 } else if s { -1.0 } else { 0.0 };
 
-        self.update(dt, glam::Vec2::new(translation_x, translation_y), rotation, speed)
+        self.update(dt, movement_input(io), rotation, speed)
+    }
+
+    /// Moves the camera to look at `target` from behind.
+    pub fn follow(&mut self, target: glam::Vec3) {
+        self.target_position = target - self.forward() * FOLLOW_DISTANCE + glam::Vec3::new(0.0, FOLLOW_HEIGHT, 0.0);
+    }
+
+    /// The direction the camera is looking in.
+    pub fn forward(&self) -> glam::Vec3 {
+        let pitch_transform = glam::Mat4::from_axis_angle(glam::Vec3::new(1.0, 0.0, 0.0), self.pitch);
+        let azimuth_transform = glam::Mat4::from_axis_angle(glam::Vec3::new(0.0, 1.0, 0.0), self.azimuth);
+        return (pitch_transform * azimuth_transform).inverse().transform_vector3(glam::Vec3::new(0.0, 0.0, -1.0));
     }
 
     pub fn transform(&self) -> glam::Mat4 {
@@ -55,4 +63,16 @@ impl Camera {
         let azimuth_transform = glam::Mat4::from_axis_angle(glam::Vec3::new(0.0, 1.0, 0.0), self.azimuth);
         return (pitch_transform * azimuth_transform) * glam::Mat4::from_translation(-self.position);
     }
-}
\ No newline at end of file
+}
+
+/// WASD as a vector, with X to the right and Y forwards.
+pub fn movement_input(io: &Io) -> glam::Vec2 {
+    let w = io.keys_down[('w' as usize - 'a' as usize) + 10];
+    let a = io.keys_down[('a' as usize - 'a' as usize) + 10];
+    let s = io.keys_down[('s' as usize - 'a' as usize) + 10];
+    let d = io.keys_down[('d' as usize - 'a' as usize) + 10];
+
+    let translation_x = if a { -1.0 } else if d { 1.0 } else { 0.0 };
+    let translation_y = if w { 1.0 } else if s { -1.0 } else { 0.0 };
+    glam::Vec2::new(translation_x, translation_y)
+}
@@ -0,0 +1,33 @@
+use glam::*;
+use specs::prelude::*;
+
+use crate::common::*;
+
+const WALK_SPEED: f32 = 8.0;
+const JUMP_SPEED: f32 = 6.0;
+
+/// Turns `PlayerInput` into the velocity every `CharacterController` tries to move at.
+pub struct CharacterSystem;
+
+impl<'a> System<'a> for CharacterSystem {
+    type SystemData = (Read<'a, DeltaTime>,
+                       Read<'a, PlayerInput>,
+                       WriteStorage<'a, CharacterController>);
+
+    fn run(&mut self, (dt, input, mut controllers): Self::SystemData) {
+        for controller in (&mut controllers).join() {
+            let walk = input.movement * WALK_SPEED;
+
+            // Grounded characters keep pressing down so they stay stuck to slopes and steps.
+            let vertical = if controller.grounded && input.jump {
+                JUMP_SPEED
+            } else if controller.grounded {
+                controller.gravity * dt.0
+            } else {
+                controller.velocity.y() + controller.gravity * dt.0
+            };
+
+            controller.velocity = Vec3::new(walk.x(), vertical, walk.z());
+        }
+    }
+}
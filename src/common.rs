@@ -1,18 +1,150 @@
 use glam::*;
 use physx::prelude::BodyHandle;
-use specs::{Component, VecStorage};
+use specs::{Component, Entity, FlaggedStorage, NullStorage, VecStorage};
 
-pub enum Mesh {
-    Cube,
-    Plane,
-    Sphere,
-}
+/// A model registered with the `MeshRegistry`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Mesh(pub(crate) usize);
+
+/// An image registered with the `TextureRegistry`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Texture(pub(crate) usize);
 
 #[derive(Default)]
 pub struct DeltaTime(pub f32);
 
+pub struct DebugLine {
+    pub start: Vec3,
+    pub end: Vec3,
+    pub color: Vec3,
+}
+
+/// PhysX's debug visualization of shapes, contacts, joints and bounds. Filled in by the physics
+/// system after each step while `enabled` is set.
+#[derive(Default)]
+pub struct DebugLines {
+    pub enabled: bool,
+    pub lines: Vec<DebugLine>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ContactKind {
+    Begin,
+    Persist,
+    End,
+}
+
+#[derive(Clone, Copy)]
+pub struct ContactPoint {
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub impulse: Vec3,
+    pub separation: f32,
+}
+
+/// Published by the physics system into an `EventChannel<ContactEvent>` resource. Every pair reports
+/// `Begin` and `End`; `Persist` is only reported for pairs where either entity has `ContactReports`.
+pub struct ContactEvent {
+    pub kind: ContactKind,
+    pub entities: (Entity, Entity),
+    /// Empty for `ContactKind::End`, and unless either entity has `ContactReports`.
+    pub points: Vec<ContactPoint>,
+}
+
+/// Asks for `ContactKind::Persist` events and contact points on the entity's contacts, which cost
+/// extra work every step for as long as the contact lasts.
+#[derive(Default)]
+pub struct ContactReports;
+
+impl Component for ContactReports {
+    type Storage = NullStorage<Self>;
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TriggerKind {
+    Enter,
+    Exit,
+}
+
+/// Published by the physics system into an `EventChannel<TriggerEvent>` resource.
+pub struct TriggerEvent {
+    pub kind: TriggerKind,
+    /// The entity with the `TriggerVolume`.
+    pub trigger: Entity,
+    pub other: Entity,
+}
+
+/// What the player is asking their character to do this frame.
+#[derive(Default)]
+pub struct PlayerInput {
+    /// Horizontal world-space direction, no longer than 1.
+    pub movement: Vec3,
+    pub jump: bool,
+}
+
+/// How far rendering is between the last two physics steps, from 0 to 1.
+#[derive(Default)]
+pub struct InterpolationAlpha(pub f32);
+
+/// Asks the main loop to save the physics state to disk, or load it back, once the frame is drawn.
+#[derive(Default)]
+pub struct SnapshotRequest {
+    pub save: bool,
+    pub restore: bool,
+}
+
+/// Half-extents of a box collider, scaled by the entity's `Transform`.
 pub struct BoxCollider(pub Vec3);
 
+/// Radius of a sphere collider, scaled by the largest axis of the entity's `Transform`.
+pub struct SphereCollider(pub f32);
+
+impl Component for SphereCollider {
+    type Storage = VecStorage<Self>;
+}
+
+/// Capsule collider running along the local Y axis. `half_height` excludes the rounded caps.
+pub struct CapsuleCollider {
+    pub radius: f32,
+    pub half_height: f32,
+}
+
+impl Component for CapsuleCollider {
+    type Storage = VecStorage<Self>;
+}
+
+/// Convex hull cooked from the OBJ data of a model, as registered with the `MeshRegistry`.
+pub struct ConvexMeshCollider(pub &'static [u8]);
+
+impl Component for ConvexMeshCollider {
+    type Storage = VecStorage<Self>;
+}
+
+/// Non-convex collision cooked from the triangles of an OBJ model. Needs a `Rigidbody` to be
+/// simulated, which is always static whatever its `BodyType`.
+pub struct StaticMeshCollider(pub &'static [u8]);
+
+impl Component for StaticMeshCollider {
+    type Storage = VecStorage<Self>;
+}
+
+/// A volume that reports overlaps through an `EventChannel<TriggerEvent>` instead of colliding,
+/// scaled like the matching collider. Usually paired with a static or kinematic `Rigidbody`. It must
+/// be the entity's only collider; alongside another one, the other is used and the trigger ignored.
+pub enum TriggerVolume {
+    /// Half-extents.
+    Box(Vec3),
+    /// Radius.
+    Sphere(f32),
+}
+
+impl Component for TriggerVolume {
+    type Storage = VecStorage<Self>;
+}
+
+/// Size of a plane collider along the local X and Z axes, scaled by the entity's `Transform`.
+/// The plane faces local +Y; an infinite size makes it an unbounded PhysX plane. Needs a `Rigidbody`
+/// to be simulated, which is always static whatever its `BodyType`.
 pub struct PlaneCollider(pub Vec2);
 
 impl Component for PlaneCollider {
@@ -25,18 +157,236 @@ impl Component for Transform {
     type Storage = VecStorage<Self>;
 }
 
+/// The `Transform` before the latest physics step, kept for render interpolation.
+pub struct PreviousTransform(pub Mat4);
+
+impl Component for PreviousTransform {
+    type Storage = VecStorage<Self>;
+}
+
 pub struct MeshRenderer(pub Mesh);
 
 impl Component for MeshRenderer {
     type Storage = VecStorage<Self>;
 }
 
+/// How a `MeshRenderer` is shaded. Entities without one use the default green.
+#[derive(Clone, Copy, Debug)]
+pub struct Material {
+    pub color: Vec3,
+    /// From 0 for a sharp highlight to 1 for none at all.
+    pub roughness: f32,
+    /// How much of `color` shows regardless of lighting.
+    pub emissive: f32,
+    /// Multiplied with `color`, using the mesh's texture coordinates.
+    pub texture: Option<Texture>,
+}
+
+impl Material {
+    /// A fully rough material, e.g. `Material::from_color(colors::RED)`.
+    pub fn from_color(color: (f32, f32, f32)) -> Material {
+        return Material { color: color.into(), ..Material::default() };
+    }
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        return Material { color: Vec3::new(0.0, 1.0, 0.0), roughness: 1.0, emissive: 0.0, texture: None };
+    }
+}
+
+impl Component for Material {
+    type Storage = VecStorage<Self>;
+}
+
 impl Component for BoxCollider {
     type Storage = VecStorage<Self>;
 }
 
-pub struct Rigidbody(pub Option<BodyHandle>);
+/// Density of a rigidbody's colliders, used by PhysX to derive its mass and inertia.
+pub struct Density(pub f32);
 
-impl Component for Rigidbody {
+impl Component for Density {
     type Storage = VecStorage<Self>;
 }
+
+/// Moves the entity's rigid body or character controller to its current `Transform` on the next physics
+/// step, then removes itself. Waits for the body or controller to be created if it doesn't exist yet.
+#[derive(Default)]
+pub struct Teleport;
+
+impl Component for Teleport {
+    type Storage = NullStorage<Self>;
+}
+
+/// Wakes the entity's dynamic body on the next physics step, then removes itself.
+#[derive(Default)]
+pub struct WakeUp;
+
+impl Component for WakeUp {
+    type Storage = NullStorage<Self>;
+}
+
+/// Marks a dynamic body that PhysX has put to sleep. Maintained by the physics system; sleeping
+/// bodies don't move, so their `Transform` isn't written until they wake.
+#[derive(Default)]
+pub struct Sleeping;
+
+impl Component for Sleeping {
+    type Storage = NullStorage<Self>;
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BodyType {
+    Dynamic,
+    /// Moved by its `Transform` rather than by forces, pushing dynamic bodies out of the way.
+    Kinematic,
+    /// Never moves. Triangle mesh and plane colliders are always static, whatever body type they're
+    /// given; the physics system switches their `Rigidbody` to this when it creates the body.
+    Static,
+}
+
+/// Force applied to a dynamic body every physics step while present.
+pub struct ApplyForce(pub Vec3);
+
+impl Component for ApplyForce {
+    type Storage = VecStorage<Self>;
+}
+
+/// Impulse applied to a dynamic body on the next physics step, then removed.
+pub struct ApplyImpulse(pub Vec3);
+
+impl Component for ApplyImpulse {
+    type Storage = VecStorage<Self>;
+}
+
+/// Overrides a dynamic body's linear velocity on the next physics step, then removed.
+pub struct SetLinearVelocity(pub Vec3);
+
+impl Component for SetLinearVelocity {
+    type Storage = VecStorage<Self>;
+}
+
+/// Velocity of a dynamic body, written by the physics system after each step.
+pub struct Velocity {
+    pub linear: Vec3,
+    pub angular: Vec3,
+}
+
+impl Component for Velocity {
+    type Storage = VecStorage<Self>;
+}
+
+/// Surface properties of a collider. Bodies without one use the default.
+pub struct PhysicsMaterial {
+    pub static_friction: f32,
+    pub dynamic_friction: f32,
+    pub restitution: f32,
+}
+
+impl Default for PhysicsMaterial {
+    fn default() -> PhysicsMaterial {
+        return PhysicsMaterial { static_friction: 0.5, dynamic_friction: 0.5, restitution: 0.2 };
+    }
+}
+
+impl Component for PhysicsMaterial {
+    type Storage = VecStorage<Self>;
+}
+
+/// The layers a collider is in (`group`) and collides with (`mask`). Two colliders only touch when each
+/// one's group overlaps the other's mask. Colliders without one are in layer 1 and collide with everything.
+#[derive(Clone, Copy)]
+pub struct CollisionLayer {
+    pub group: u32,
+    pub mask: u32,
+}
+
+impl Default for CollisionLayer {
+    fn default() -> CollisionLayer {
+        return CollisionLayer { group: 1, mask: !0 };
+    }
+}
+
+impl Component for CollisionLayer {
+    type Storage = VecStorage<Self>;
+}
+
+pub enum JointKind {
+    Fixed,
+    /// Rotates about the anchor's X axis, optionally limited to a `(lower, upper)` angle in radians.
+    Revolute(Option<(f32, f32)>),
+    Spherical,
+    /// Slides along the anchor's X axis, optionally limited to a `(lower, upper)` distance.
+    Prismatic(Option<(f32, f32)>),
+    /// Keeps the bodies between `min` and `max` apart.
+    Distance { min: f32, max: f32 },
+}
+
+/// Connects the rigid bodies of two entities, or one entity and the world. Lives on its own entity
+/// so a body can have any number of joints; removing it releases the joint. Changing it, or either
+/// body being recreated, creates the joint again from the current poses.
+pub struct Joint {
+    pub kind: JointKind,
+    pub first: Entity,
+    /// `None` pins `first` to the world.
+    pub second: Option<Entity>,
+    /// Where the joint is in world space, taken when both bodies exist.
+    pub anchor: Mat4,
+}
+
+impl Component for Joint {
+    type Storage = FlaggedStorage<Self, VecStorage<Self>>;
+}
+
+/// A capsule that walks rather than being simulated, centred on the entity's `Transform`.
+/// Driven by `CharacterSystem` and moved by the physics system; removing it releases the controller.
+pub struct CharacterController {
+    pub radius: f32,
+    /// Half the length of the capsule's middle section, excluding the caps.
+    pub half_height: f32,
+    /// The tallest ledge the character can walk up.
+    pub step_offset: f32,
+    /// The steepest walkable slope, in radians.
+    pub slope_limit: f32,
+    pub gravity: f32,
+    /// What the character tries to move at on the next physics step.
+    pub velocity: Vec3,
+    /// Whether the last move ended standing on something, written by the physics system.
+    pub grounded: bool,
+}
+
+impl CharacterController {
+    pub fn new(radius: f32, half_height: f32) -> CharacterController {
+        return CharacterController {
+            radius,
+            half_height,
+            step_offset: 0.5,
+            slope_limit: std::f32::consts::FRAC_PI_4,
+            gravity: -9.81,
+            velocity: Vec3::zero(),
+            grounded: false,
+        };
+    }
+}
+
+impl Component for CharacterController {
+    type Storage = FlaggedStorage<Self, VecStorage<Self>>;
+}
+
+/// Removing this (or its entity) releases the PhysX actor. Replacing it or changing its `body_type`
+/// recreates the actor at the entity's `Transform`, dropping its velocity.
+pub struct Rigidbody {
+    pub handle: Option<BodyHandle>,
+    pub body_type: BodyType,
+}
+
+impl Rigidbody {
+    pub fn new(body_type: BodyType) -> Rigidbody {
+        return Rigidbody { handle: None, body_type };
+    }
+}
+
+impl Component for Rigidbody {
+    type Storage = FlaggedStorage<Self, VecStorage<Self>>;
+}
@@ -0,0 +1,109 @@
+use std::io::{Error, ErrorKind};
+
+use glam::*;
+
+/// Scene-wide simulation settings. Changing the resource reconfigures the running scene, apart from
+/// `threads`, which only applies when the scene is created.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhysicsConfig {
+    pub gravity: Vec3,
+    /// Worker threads simulating the scene.
+    pub threads: u32,
+    pub position_iterations: u32,
+    pub velocity_iterations: u32,
+    /// Relative speed below which contacts stop bouncing.
+    pub bounce_threshold: f32,
+    /// Mass-normalized kinetic energy below which dynamic bodies may fall asleep.
+    pub sleep_threshold: f32,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> PhysicsConfig {
+        return PhysicsConfig {
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            threads: 8,
+            position_iterations: 4,
+            velocity_iterations: 1,
+            bounce_threshold: 2.0,
+            sleep_threshold: 0.005,
+        };
+    }
+}
+
+impl PhysicsConfig {
+    /// Reads `key = value` lines, e.g. `threads = 2` or `gravity = 0 -9.81 0`. Keys that are left out
+    /// keep their defaults, and `#` starts a comment.
+    pub fn load(path: &str) -> std::io::Result<PhysicsConfig> {
+        return PhysicsConfig::parse(&std::fs::read_to_string(path)?);
+    }
+
+    pub fn parse(source: &str) -> std::io::Result<PhysicsConfig> {
+        let mut config = PhysicsConfig::default();
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let invalid = |message: &str| Error::new(ErrorKind::InvalidData, format!("line {}: {}", number + 1, message));
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim();
+            let value = parts.next().ok_or_else(|| invalid("expected `key = value`"))?.trim();
+
+            let floats: Vec<f32> = value.split_whitespace().map(|v| v.parse()).collect::<Result<_, _>>()
+                .map_err(|_| invalid("expected numbers"))?;
+            let float = || match floats[..] {
+                [v] => Ok(v),
+                _ => Err(invalid("expected a single number")),
+            };
+            let integer = || value.parse::<u32>().map_err(|_| invalid("expected a whole number"));
+
+            match key {
+                "gravity" => match floats[..] {
+                    [x, y, z] => config.gravity = Vec3::new(x, y, z),
+                    _ => return Err(invalid("expected three numbers")),
+                },
+                "threads" => config.threads = integer()?,
+                "position_iterations" => config.position_iterations = integer()?,
+                "velocity_iterations" => config.velocity_iterations = integer()?,
+                "bounce_threshold" => config.bounce_threshold = float()?,
+                "sleep_threshold" => config.sleep_threshold = float()?,
+                _ => return Err(invalid(&format!("unknown setting `{}`", key))),
+            }
+        }
+
+        return Ok(config);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_override_defaults_and_comments_are_ignored() {
+        let config = PhysicsConfig::parse("# Low gravity\ngravity = 0 -1.6 0\n\nthreads = 2  # fewer\nsleep_threshold = 0.01\n").expect("settings parse");
+        assert_eq!(config, PhysicsConfig {
+            gravity: Vec3::new(0.0, -1.6, 0.0),
+            threads: 2,
+            sleep_threshold: 0.01,
+            ..PhysicsConfig::default()
+        });
+    }
+
+    #[test]
+    fn the_shipped_settings_parse() {
+        assert!(PhysicsConfig::parse(include_str!("../resources/physics.cfg")).is_ok());
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = |source: &str| PhysicsConfig::parse(source).expect_err("settings are invalid").to_string();
+        assert_eq!(error("threads = 2\ngravity = 0 -9.81\n"), "line 2: expected three numbers");
+        assert_eq!(error("threads = -1\n"), "line 1: expected a whole number");
+        assert_eq!(error("# comment\n\nfriction = 1\n"), "line 3: unknown setting `friction`");
+        assert_eq!(error("bounce_threshold\n"), "line 1: expected `key = value`");
+        assert_eq!(error("bounce_threshold = fast\n"), "line 1: expected numbers");
+    }
+}
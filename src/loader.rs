@@ -1,45 +1,260 @@
-use glium::Display;
-use glium::vertex::VertexBufferAny;
-use genmesh::{MapToVertices, EmitTriangles};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::BufRead;
 
-#[derive(Copy, Clone)]
-struct Vertex {
+use genmesh::{EmitTriangles, Quad, Triangle};
+
+#[derive(Copy, Clone, Debug)]
+pub struct Vertex {
     position: [f32; 3],
     normal: [f32; 3],
-    texture: [f32; 2],
+    tex_coords: [f32; 2],
 }
 
-implement_vertex!(Vertex, position, normal, texture);
+implement_vertex!(Vertex, position, normal, tex_coords);
+
+/// Unique vertices and the triangle list indexing into them.
+pub struct IndexedMesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    /// The model couldn't be read or isn't valid OBJ.
+    Io(std::io::Error),
+    /// A vertex or face statement is malformed, on the given line.
+    InvalidLine(usize),
+    /// A face refers to a position, texture coordinate or normal that isn't in the file.
+    MissingVertex,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(error) => write!(f, "couldn't read OBJ: {}", error),
+            LoadError::InvalidLine(line) => write!(f, "line {} isn't valid OBJ", line),
+            LoadError::MissingVertex => write!(f, "a face refers to a vertex that doesn't exist"),
+        }
+    }
+}
 
-pub fn load_wavefront(display: &Display, data: &[u8]) -> VertexBufferAny {
-    let mut data = ::std::io::BufReader::new(data);
-    let data = obj::Obj::load_buf(&mut data).unwrap();
+impl std::error::Error for LoadError {}
+
+impl From<std::io::Error> for LoadError {
+    fn from(error: std::io::Error) -> LoadError {
+        return LoadError::Io(error);
+    }
+}
+
+/// Reads the triangles of an OBJ model, ready for a vertex and index buffer. Face corners sharing
+/// a position, texture coordinate and normal become one vertex.
+pub fn load_wavefront(data: &[u8]) -> Result<IndexedMesh, LoadError> {
+    let data = parse(data)?;
 
     let mut vertex_data = Vec::new();
+    let mut indices = Vec::new();
+    let mut unique = HashMap::new();
+    let mut triangles = Vec::new();
 
     for object in data.objects.iter() {
         for polygon in object.groups.iter().flat_map(|g| g.polys.iter()) {
-            match polygon {
-                &genmesh::Polygon::PolyTri(genmesh::Triangle { x: v1, y: v2, z: v3 }) => {
-                    for v in [v1, v2, v3].iter() {
-                        let position = data.position[v.0];
-                        let texture = v.1.map(|index| data.texture[index]);
-                        let normal = v.2.map(|index| data.normal[index]);
-
-                        let texture = texture.unwrap_or([0.0, 0.0]);
-                        let normal = normal.unwrap_or([0.0, 0.0, 0.0]);
-
-                        vertex_data.push(Vertex {
-                            position: position,
-                            normal: normal,
-                            texture: texture,
-                        })
+            triangles.clear();
+            triangulate(polygon, &mut triangles);
+            for triangle in triangles.iter() {
+                for v in triangle.iter() {
+                    if let Some(index) = unique.get(v) {
+                        indices.push(*index);
+                        continue;
                     }
-                },
-                _ => unimplemented!()
+
+                    let position = *data.position.get(v.0).ok_or(LoadError::MissingVertex)?;
+                    let texture = match v.1 {
+                        Some(index) => *data.texture.get(index).ok_or(LoadError::MissingVertex)?,
+                        None => [0.0, 0.0],
+                    };
+                    let normal = match v.2 {
+                        Some(index) => *data.normal.get(index).ok_or(LoadError::MissingVertex)?,
+                        None => [0.0, 0.0, 0.0],
+                    };
+
+                    let index = vertex_data.len() as u32;
+                    vertex_data.push(Vertex {
+                        position,
+                        normal,
+                        tex_coords: texture,
+                    });
+                    unique.insert(*v, index);
+                    indices.push(index);
+                }
+            }
+        }
+    }
+
+    return Ok(IndexedMesh { vertices: vertex_data, indices });
+}
+
+/// Reads only the vertex positions of an OBJ model, for cooking collision geometry.
+pub fn load_positions(data: &[u8]) -> Result<Vec<[f32; 3]>, LoadError> {
+    return Ok(parse(data)?.position);
+}
+
+/// Vertex positions and the triangles indexing into them.
+pub type Triangles = (Vec<[f32; 3]>, Vec<[u32; 3]>);
+
+/// Reads the vertex positions and triangles of an OBJ model, for cooking collision geometry.
+pub fn load_triangles(data: &[u8]) -> Result<Triangles, LoadError> {
+    let data = parse(data)?;
+
+    let mut triangles = Vec::new();
+    let mut corners = Vec::new();
+
+    for object in data.objects.iter() {
+        for polygon in object.groups.iter().flat_map(|g| g.polys.iter()) {
+            corners.clear();
+            triangulate(polygon, &mut corners);
+            for triangle in corners.iter() {
+                if triangle.iter().any(|v| v.0 >= data.position.len()) {
+                    return Err(LoadError::MissingVertex);
+                }
+                triangles.push([triangle[0].0 as u32, triangle[1].0 as u32, triangle[2].0 as u32]);
             }
         }
     }
 
-    glium::vertex::VertexBuffer::new(display, &vertex_data).unwrap().into()
-}
\ No newline at end of file
+    return Ok((data.position, triangles));
+}
+
+/// Faces are read as plain index lists so that any polygon loads, not just triangles and quads.
+fn parse(data: &[u8]) -> Result<obj::Obj<'static, obj::SimplePolygon>, LoadError> {
+    let source = sanitize(data)?;
+    return Ok(obj::Obj::load_buf(&mut source.as_bytes())?);
+}
+
+/// The obj crate panics on statements it doesn't know and on malformed vertices, so lines are checked
+/// before it sees them. Statements we have no use for, like lines, points, curves and material
+/// libraries, are dropped; everything else is passed on with its whitespace tidied up.
+fn sanitize(data: &[u8]) -> Result<String, LoadError> {
+    let mut source = String::with_capacity(data.len());
+
+    for (number, line) in data.lines().enumerate() {
+        let line = line?;
+        let invalid = || LoadError::InvalidLine(number + 1);
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let words: Vec<&str> = words.collect();
+
+        match keyword {
+            "v" | "vn" | "vt" => {
+                let needed = if keyword == "vt" { 2 } else { 3 };
+                // Texture coordinates may leave out v, and positions may have a w we don't use.
+                let mut floats = words.iter().map(|w| w.parse::<f32>()).collect::<Result<Vec<f32>, _>>()
+                    .map_err(|_| invalid())?;
+                if keyword == "vt" && floats.len() == 1 {
+                    floats.push(0.0);
+                }
+                if floats.len() < needed {
+                    return Err(invalid());
+                }
+                let floats: Vec<String> = floats[..needed].iter().map(|f| f.to_string()).collect();
+                source.push_str(&format!("{} {}\n", keyword, floats.join(" ")));
+            }
+            "f" => {
+                if words.len() < 3 || !words.iter().all(|w| is_face_corner(w)) {
+                    return Err(invalid());
+                }
+                source.push_str(&format!("f {}\n", words.join(" ")));
+            }
+            "o" | "g" | "s" | "usemtl" => source.push_str(&format!("{} {}\n", keyword, words.join(" "))),
+            _ => {}
+        }
+    }
+
+    return Ok(source);
+}
+
+/// A face corner is `p`, `p/t`, `p//n` or `p/t/n`, with 1-based or negative indices.
+fn is_face_corner(corner: &str) -> bool {
+    let index = |part: &str| match part.parse::<isize>() {
+        Ok(index) => index != 0,
+        Err(_) => false,
+    };
+
+    let parts: Vec<&str> = corner.split('/').collect();
+    match parts[..] {
+        [p] => index(p),
+        [p, t] => index(p) && index(t),
+        [p, t, n] => index(p) && (t.is_empty() || index(t)) && index(n),
+        _ => false,
+    }
+}
+
+/// Splits a polygon into triangles, appending them to `triangles`. Triangles and quads go through
+/// genmesh; it has nothing for larger polygons, so those are fanned out from their first corner,
+/// which suits the convex faces modelling tools export.
+fn triangulate(polygon: &obj::SimplePolygon, triangles: &mut Vec<[obj::IndexTuple; 3]>) {
+    let mut emit = |t: Triangle<obj::IndexTuple>| triangles.push([t.x, t.y, t.z]);
+
+    match polygon[..] {
+        [x, y, z] => Triangle::new(x, y, z).emit_triangles(&mut emit),
+        [x, y, z, w] => Quad::new(x, y, z, w).emit_triangles(&mut emit),
+        _ => {
+            for i in 1..polygon.len().saturating_sub(1) {
+                emit(Triangle::new(polygon[0], polygon[i], polygon[i + 1]));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE_CORNERS: &str = "v 0 0 0\nv 1 0 0\nv 1 0 1\nv 0 0 1\nv 0.5 0 1.5\n";
+
+    #[test]
+    fn quads_load_as_two_triangles() {
+        let mesh = load_wavefront(format!("{}f 1 2 3 4\n", SQUARE_CORNERS).as_bytes()).expect("quad loads");
+        assert_eq!(mesh.indices, vec![0, 1, 2, 2, 3, 0]);
+        assert_eq!(mesh.vertices.len(), 4);
+    }
+
+    #[test]
+    fn pentagons_load_as_three_triangles() {
+        let mesh = load_wavefront(format!("{}f 1 2 3 5 4\n", SQUARE_CORNERS).as_bytes()).expect("pentagon loads");
+        assert_eq!(mesh.indices.len(), 9);
+        assert_eq!(mesh.vertices.len(), 5);
+
+        let (_, triangles) = load_triangles(format!("{}f 1 2 3 5 4\n", SQUARE_CORNERS).as_bytes()).expect("pentagon loads");
+        assert_eq!(triangles, vec![[0, 1, 2], [0, 2, 4], [0, 4, 3]]);
+    }
+
+    #[test]
+    fn faces_outside_the_vertex_list_are_an_error() {
+        let data = format!("{}f 1 2 9\n", SQUARE_CORNERS);
+        match load_wavefront(data.as_bytes()) {
+            Err(LoadError::MissingVertex) => {}
+            other => panic!("expected MissingVertex, got {:?}", other.map(|mesh| mesh.indices)),
+        }
+        match load_triangles(data.as_bytes()) {
+            Err(LoadError::MissingVertex) => {}
+            other => panic!("expected MissingVertex, got {:?}", other.map(|(_, triangles)| triangles)),
+        }
+    }
+
+    #[test]
+    fn unsupported_statements_are_skipped_and_malformed_ones_are_an_error() {
+        let data = format!("mtllib scene.mtl\n{}vp 0.5\nl 1 2\nf 1 2 3\n", SQUARE_CORNERS);
+        assert_eq!(load_wavefront(data.as_bytes()).expect("loads without lines").indices.len(), 3);
+
+        for (source, line) in [("v 0 0\n", 1), ("v 0 0 0\nvn a b c\n", 2), ("v 0 0 0\nf 0 1 1\n", 2)].iter() {
+            match load_wavefront(source.as_bytes()) {
+                Err(LoadError::InvalidLine(number)) => assert_eq!(number, *line),
+                other => panic!("expected InvalidLine({}), got {:?}", line, other.map(|mesh| mesh.indices)),
+            }
+        }
+    }
+}
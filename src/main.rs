@@ -15,54 +15,268 @@ use std::time::{Duration, Instant};
 
 mod colors;
 mod common;
+mod config;
 mod camera;
 mod support;
 mod physics;
+mod character;
+mod queries;
 mod rendering;
+mod snapshot;
 mod loader;
+mod meshes;
+mod textures;
+
+/// Where the physics settings are read from, unless overridden by the `PHYSICS_CONFIG` environment variable.
+const PHYSICS_CONFIG_PATH: &str = "resources/physics.cfg";
+
+/// Where the physics window's snapshot buttons save to and restore from.
+const SNAPSHOT_PATH: &str = "physics.snapshot";
+
+const DEBRIS_LAYER: u32 = 1 << 1;
+
+const FIXED_TIMESTEP: f32 = 1.0 / 60.0;
+
+/// The most physics steps taken per rendered frame.
+const MAX_SUBSTEPS: u32 = 4;
 
 fn main() {
     let mut world = World::new();
     world.register::<Transform>();
+    world.register::<PreviousTransform>();
     world.register::<BoxCollider>();
+    world.register::<SphereCollider>();
+    world.register::<CapsuleCollider>();
+    world.register::<ConvexMeshCollider>();
+    world.register::<StaticMeshCollider>();
     world.register::<Rigidbody>();
+    world.register::<Teleport>();
+    world.register::<WakeUp>();
+    world.register::<Sleeping>();
+    world.register::<ApplyForce>();
+    world.register::<ApplyImpulse>();
+    world.register::<SetLinearVelocity>();
+    world.register::<Velocity>();
+    world.register::<Density>();
+    world.register::<PhysicsMaterial>();
+    world.register::<CollisionLayer>();
+    world.register::<ContactReports>();
+    world.register::<Joint>();
+    world.register::<CharacterController>();
     world.register::<PlaneCollider>();
+    world.register::<TriggerVolume>();
     world.register::<MeshRenderer>();
+    world.register::<Material>();
+
+    let config_path = std::env::var("PHYSICS_CONFIG").unwrap_or_else(|_| PHYSICS_CONFIG_PATH.to_string());
+    let physics_config = config::PhysicsConfig::load(&config_path).unwrap_or_else(|error| {
+        println!("Using default physics settings, couldn't load {}: {}", config_path, error);
+        config::PhysicsConfig::default()
+    });
+
+    let mut meshes = meshes::MeshRegistry::default();
+    let cube = meshes.insert("cube", include_bytes!("../resources/models/cube.obj")).expect("shipped models load");
+    let sphere = meshes.insert("sphere", include_bytes!("../resources/models/sphere.obj")).expect("shipped models load");
+    let plane = meshes.insert("plane", include_bytes!("../resources/models/plane.obj")).expect("shipped models load");
+    // The ground would only shadow itself.
+    meshes.set_casts_shadows(plane, false);
+
+    let mut textures = textures::TextureRegistry::default();
+    let checker = textures.insert("checker", include_bytes!("../resources/textures/checker.png")).expect("checker texture decodes");
+
+    // Scaled and lifted to stand on the ground.
+    let showcase: [(&str, &'static [u8], Mat4); 4] = [
+        ("cylinder", include_bytes!("../resources/models/cylinder.obj"), Mat4::from_translation(Vec3::new(-45.0, 4.0, -120.0)) * Mat4::from_scale(Vec3::splat(4.0))),
+        ("hemisphere", include_bytes!("../resources/models/hemisphere.obj"), Mat4::from_translation(Vec3::new(-15.0, 0.0, -120.0)) * Mat4::from_scale(Vec3::splat(4.0))),
+        ("man", include_bytes!("../resources/models/man.obj"), Mat4::from_translation(Vec3::new(15.0, 0.3, -120.0))),
+        ("teapot", include_bytes!("../resources/models/teapot.obj"), Mat4::from_translation(Vec3::new(45.0, 2.0, -120.0)) * Mat4::from_scale(Vec3::splat(0.05))),
+    ];
+
+    let mut physics_system = physics::PhysicsSystem::new(&physics_config);
+    world.insert(physics_config);
+    System::setup(&mut physics_system, &mut world);
 
     let mut randy = rand::thread_rng();
 
     for _ in 1..10000 {
-        world.create_entity()
-            .with(Transform(Mat4::from_translation(Vec3::new(randy.gen_range(-200.0, 200.0), randy.gen_range(20.0, 1000.0), randy.gen_range(-200.0, 200.0)))))
-            .with(BoxCollider(Vec3::new(1.0, 1.0, 1.0)))
-            .with(MeshRenderer(Mesh::Cube))
-            .with(Rigidbody(None)).build();
+        let position = Vec3::new(randy.gen_range(-200.0, 200.0), randy.gen_range(20.0, 1000.0), randy.gen_range(-200.0, 200.0));
+        let builder = world.create_entity().with(Rigidbody::new(BodyType::Dynamic));
+
+        match randy.gen_range(0, 10) {
+            0..=5 => builder
+                .with(Transform(Mat4::from_translation(position)))
+                .with(BoxCollider(Vec3::new(1.0, 1.0, 1.0)))
+                .with(MeshRenderer(cube)),
+            6..=7 => builder
+                .with(Transform(Mat4::from_translation(position)))
+                .with(SphereCollider(1.0))
+                .with(PhysicsMaterial { static_friction: 0.8, dynamic_friction: 0.8, restitution: 0.8 })
+                .with(MeshRenderer(sphere))
+                .with(Material { color: colors::ORANGE.into(), roughness: 0.2, ..Material::default() }),
+            8 => builder
+                .with(Transform(Mat4::from_translation(position) * Mat4::from_scale(Vec3::new(1.0, 2.0, 1.0))))
+                .with(CapsuleCollider { radius: 1.0, half_height: 0.5 })
+                // Debris passes through other debris but still lands on everything else.
+                .with(CollisionLayer { group: DEBRIS_LAYER, mask: !DEBRIS_LAYER })
+                .with(MeshRenderer(sphere))
+                .with(Material::from_color(colors::GRAY)),
+            _ => builder
+                .with(Transform(Mat4::from_translation(position) * Mat4::from_scale(Vec3::new(2.0, 1.0, 1.0))))
+                .with(ConvexMeshCollider(include_bytes!("../resources/models/sphere.obj")))
+                .with(MeshRenderer(sphere))
+                .with(Material::from_color(colors::GREEN2)),
+        }.build();
     }
 
     world.create_entity()
         .with(Transform(Mat4::from_translation(Vec3::zero()) * Mat4::from_scale(Vec3::new(1000.0, 1.0, 1000.0))))
-        .with(MeshRenderer(Mesh::Plane))
-        .with(PlaneCollider(Vec2::new(1000.0,  1000.0))).build();
+        .with(MeshRenderer(plane))
+        .with(Material { texture: Some(checker), ..Material::from_color(colors::GRAY) })
+        .with(Rigidbody::new(BodyType::Static))
+        .with(PlaneCollider(Vec2::new(1.0, 1.0))).build();
+
+    world.create_entity()
+        .with(Transform(Mat4::from_translation(Vec3::new(-100.0, 30.0, 0.0)) * Mat4::from_rotation_z(-0.3) * Mat4::from_scale(Vec3::new(150.0, 1.0, 150.0))))
+        .with(MeshRenderer(plane))
+        // Glossy, to match how slippery it is.
+        .with(Material { color: Vec3::new(0.7, 0.85, 1.0), roughness: 0.1, ..Material::default() })
+        .with(Rigidbody::new(BodyType::Static))
+        .with(PhysicsMaterial { static_friction: 0.05, dynamic_friction: 0.02, restitution: 0.0 })
+        .with(PlaneCollider(Vec2::new(1.0, 1.0))).build();
+
+    world.create_entity()
+        .with(Transform(Mat4::from_translation(Vec3::new(100.0, 0.0, 0.0)) * Mat4::from_scale(Vec3::new(30.0, 30.0, 30.0))))
+        .with(MeshRenderer(sphere))
+        .with(Rigidbody::new(BodyType::Static))
+        .with(StaticMeshCollider(include_bytes!("../resources/models/sphere.obj"))).build();
+
+    for (name, data, model) in showcase.iter() {
+        world.create_entity()
+            .with(Transform(*model))
+            .with(MeshRenderer(meshes.insert(name, data).expect("shipped models load")))
+            .with(Rigidbody::new(BodyType::Static))
+            .with(StaticMeshCollider(data)).build();
+    }
+    world.insert(meshes);
+    world.insert(textures);
+
+    // The physics window counts what's inside these.
+    world.create_entity()
+        .with(Transform(Mat4::from_translation(Vec3::new(0.0, 10.0, 100.0))))
+        .with(Rigidbody::new(BodyType::Static))
+        .with(TriggerVolume::Box(Vec3::new(20.0, 10.0, 20.0))).build();
+    world.create_entity()
+        .with(Transform(Mat4::from_translation(Vec3::new(100.0, 0.0, 0.0))))
+        .with(Rigidbody::new(BodyType::Static))
+        .with(TriggerVolume::Sphere(40.0)).build();
+
+    let chain_top = Vec3::new(0.0, 60.0, -50.0);
+    let mut previous_link: Option<Entity> = None;
+    for i in 0..8 {
+        let position = chain_top - Vec3::new(0.0, 2.5 * i as f32 + 1.25, 0.0);
+        let link = world.create_entity()
+            .with(Transform(Mat4::from_translation(position)))
+            .with(MeshRenderer(sphere))
+            .with(Rigidbody::new(BodyType::Dynamic))
+            // So the physics window can show how hard the links knock into things.
+            .with(ContactReports)
+            .with(SphereCollider(1.0)).build();
+
+        world.create_entity()
+            .with(Joint {
+                kind: JointKind::Spherical,
+                first: link,
+                second: previous_link,
+                anchor: Mat4::from_translation(position + Vec3::new(0.0, 1.25, 0.0)),
+            }).build();
+        previous_link = Some(link);
+    }
+
+    // Every other kind of joint, each holding a crate to the world at an anchor relative to it.
+    let joint_showcase = vec![
+        (JointKind::Fixed, Mat4::from_translation(Vec3::new(0.0, 3.0, 0.0))),
+        (JointKind::Revolute(Some((-1.5, 1.5))), Mat4::from_translation(Vec3::new(0.0, 0.0, 3.0))),
+        // Tilted, so the crate slides down to the end of its rail.
+        (JointKind::Prismatic(Some((-5.0, 5.0))), Mat4::from_rotation_z(-0.5)),
+        (JointKind::Distance { min: 0.0, max: 8.0 }, Mat4::from_translation(Vec3::new(0.0, 3.0, 0.0))),
+    ];
+    for (i, (kind, offset)) in joint_showcase.into_iter().enumerate() {
+        let position = Vec3::new(15.0 + 10.0 * i as f32, 40.0, -50.0);
+        let held = world.create_entity()
+            .with(Transform(Mat4::from_translation(position)))
+            .with(MeshRenderer(cube))
+            .with(Rigidbody::new(BodyType::Dynamic))
+            .with(BoxCollider(Vec3::new(1.0, 1.0, 1.0))).build();
+
+        world.create_entity()
+            .with(Joint { kind, first: held, second: None, anchor: Mat4::from_translation(position) * offset })
+            .build();
+    }
+
+    let elevator_scale = Mat4::from_scale(Vec3::new(20.0, 1.0, 20.0));
+    let elevator = world.create_entity()
+        .with(Transform(Mat4::from_translation(Vec3::new(0.0, 5.0, 100.0)) * elevator_scale))
+        .with(MeshRenderer(cube))
+        .with(Rigidbody::new(BodyType::Kinematic))
+        .with(BoxCollider(Vec3::new(1.0, 1.0, 1.0))).build();
+
+    world.create_entity()
+        .with(Transform(Mat4::from_scale_rotation_translation(Vec3::new(1.0, 1.5, 1.0), Quat::identity(), Vec3::new(0.0, 5.0, 30.0))))
+        .with(MeshRenderer(sphere))
+        .with(Material { color: colors::RED.into(), roughness: 0.5, emissive: 0.3, ..Material::default() })
+        .with(CharacterController::new(1.0, 0.5)).build();
 
     world.insert(DeltaTime(0.0));
+    world.insert(PlayerInput::default());
 
-    let mut physics_system = physics::PhysicsSystem::new();
+    let mut character_system = character::CharacterSystem;
     let mut rendering_system = rendering::RenderingSystem::new();
+    System::setup(&mut rendering_system, &mut world);
 
-    let frame_time = 0.016;
+    let mut accumulator = 0.0;
+    let mut elapsed: f32 = 0.0;
+    let mut last_frame = Instant::now();
 
     loop {
         let frame_start = Instant::now();
-        world.insert(DeltaTime(frame_time));
-        physics_system.run_now(&world);
+        accumulator += (frame_start - last_frame).as_secs_f32();
+        last_frame = frame_start;
+
+        let mut substeps = 0;
+        while accumulator >= FIXED_TIMESTEP && substeps < MAX_SUBSTEPS {
+            world.insert(DeltaTime(FIXED_TIMESTEP));
+
+            elapsed += FIXED_TIMESTEP;
+            if let Some(t) = world.write_storage::<Transform>().get_mut(elevator) {
+                t.0 = Mat4::from_translation(Vec3::new(0.0, 25.0 - 20.0 * elapsed.cos(), 100.0)) * elevator_scale;
+            }
+
+            character_system.run_now(&world);
+            physics_system.run_now(&world);
+            world.maintain();
+
+            accumulator -= FIXED_TIMESTEP;
+            substeps += 1;
+        }
+
+        // Drop whatever time we couldn't catch up on rather than spiralling further behind.
+        accumulator = accumulator.min(FIXED_TIMESTEP);
+
+        world.insert(InterpolationAlpha(accumulator / FIXED_TIMESTEP));
         rendering_system.run_now(&world);
+        world.maintain();
 
-        let frame_simulation_time = Instant::now() - frame_start;
-        let sleep_duration =  if frame_simulation_time < std::time::Duration::from_secs_f32(frame_time) {
-            std::time::Duration::from_secs_f32(frame_time) - frame_simulation_time
-        } else {
-            std::time::Duration::from_secs_f32(frame_time)
-        };
+        let request = std::mem::take(&mut *world.write_resource::<SnapshotRequest>());
+        if request.save {
+            if let Err(error) = std::fs::write(SNAPSHOT_PATH, physics_system.snapshot(&world).to_bytes()) {
+                println!("Couldn't save physics snapshot to {}: {}", SNAPSHOT_PATH, error);
+            }
+        }
+        if request.restore {
+            match std::fs::read(SNAPSHOT_PATH).and_then(|bytes| snapshot::PhysicsSnapshot::from_bytes(&bytes)) {
+                Ok(snapshot) => physics_system.restore(&world, &snapshot),
+                Err(error) => println!("Couldn't restore physics snapshot from {}: {}", SNAPSHOT_PATH, error),
+            }
+        }
     }
 }
-
@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+use crate::common::Mesh;
+use crate::loader::{self, LoadError, Vertex};
+
+/// The triangles behind a `Mesh` handle.
+pub struct MeshAsset {
+    pub name: String,
+    pub vertices: Vec<Vertex>,
+    /// Triangle list into `vertices`.
+    pub indices: Vec<u32>,
+    pub casts_shadows: bool,
+}
+
+/// Every model `MeshRenderer`s can draw, by name. Meshes are only ever added, so a `Mesh` handle
+/// stays valid for the life of the registry; the renderer uploads new ones as they appear.
+#[derive(Default)]
+pub struct MeshRegistry {
+    meshes: Vec<MeshAsset>,
+    names: HashMap<String, Mesh>,
+}
+
+impl MeshRegistry {
+    /// Parses OBJ data and registers it under `name`, or returns the mesh already registered there.
+    pub fn insert(&mut self, name: &str, data: &[u8]) -> Result<Mesh, LoadError> {
+        if let Some(mesh) = self.names.get(name) {
+            return Ok(*mesh);
+        }
+
+        let loaded = loader::load_wavefront(data)?;
+
+        let mesh = Mesh(self.meshes.len());
+        self.meshes.push(MeshAsset {
+            name: name.to_string(),
+            vertices: loaded.vertices,
+            indices: loaded.indices,
+            casts_shadows: true,
+        });
+        self.names.insert(name.to_string(), mesh);
+        return Ok(mesh);
+    }
+
+    pub fn set_casts_shadows(&mut self, mesh: Mesh, casts_shadows: bool) {
+        self.meshes[mesh.0].casts_shadows = casts_shadows;
+    }
+
+    pub fn asset(&self, mesh: Mesh) -> &MeshAsset {
+        &self.meshes[mesh.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.meshes.len()
+    }
+}
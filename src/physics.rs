@@ -1,41 +1,915 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::c_void;
+use std::ptr::null;
+
 use glam::*;
 use physx::prelude::*;
+use physx::rigid_body::RigidBodyFlag;
+use physx::transform::{gl_to_px_tf, gl_to_px_v3, px_to_gl_tf, px_to_gl_v3};
+use physx_sys::*;
 use specs::prelude::*;
+use specs::shrev::EventChannel;
+use specs::world::Index;
 
 use crate::common::*;
+// Named explicitly, as the physx prelude has its own `CollisionLayer`.
+use crate::common::CollisionLayer;
+use crate::config::PhysicsConfig;
+use crate::loader;
+use crate::queries::PhysicsQueries;
+use crate::snapshot::{BodySnapshot, PhysicsSnapshot};
 use stopwatch::Stopwatch;
 
 const PX_PHYSICS_VERSION: u32 = physx::version(4, 1, 1);
 
+const DEFAULT_DENSITY: f32 = 10.0;
+
+/// Set in the third word of a shape's simulation filter data when its entity has `ContactReports`.
+const REPORT_CONTACTS: u32 = 1;
+
+/// Half the thickness of the box backing a finite `PlaneCollider`.
+const PLANE_HALF_THICKNESS: f32 = 0.5;
+
+/// How far the crosses marking debug points reach from them.
+const DEBUG_POINT_SIZE: f32 = 0.1;
+
+/// What the debug visualization shows, while it's enabled.
+const DEBUG_VISUALIZATIONS: [PxVisualizationParameter::Enum; 6] = [
+    PxVisualizationParameter::eCOLLISION_SHAPES,
+    PxVisualizationParameter::eCONTACT_POINT,
+    PxVisualizationParameter::eCONTACT_NORMAL,
+    PxVisualizationParameter::eJOINT_LOCAL_FRAMES,
+    PxVisualizationParameter::eJOINT_LIMITS,
+    PxVisualizationParameter::eCOLLISION_AABBS,
+];
+
 pub struct PhysicsSystem {
-    foundation: Foundation,
     pub physics: Physics,
     pub scene: Box<Scene>,
+    px_scene: *mut PxScene,
+    dispatcher: *mut PxDefaultCpuDispatcher,
+    cooking: Cooking,
+    /// Shared between every body with the same `PhysicsMaterial`, keyed by its bits.
+    materials: HashMap<[u32; 3], *mut PxMaterial>,
+    convex_meshes: HashMap<usize, *mut PxConvexMesh>,
+    triangle_meshes: HashMap<usize, *mut PxTriangleMesh>,
+    /// Actors we've added to the scene, by the index of the entity owning them.
+    bodies: HashMap<Index, BodyHandle>,
+    rigidbody_events: Option<ReaderId<ComponentEvent>>,
+    /// Entities whose collider was too small to simulate, so they're only reported once.
+    degenerate_colliders: HashSet<Index>,
+    /// Joints we've created, by the index of the entity owning them, along with the actors they join.
+    joints: HashMap<Index, (*mut PxJoint, BodyHandle, Option<BodyHandle>)>,
+    joint_events: Option<ReaderId<ComponentEvent>>,
+    controller_manager: *mut PxControllerManager,
+    /// Character controllers we've created, by the index of the entity owning them.
+    controllers: HashMap<Index, *mut PxController>,
+    controller_events: Option<ReaderId<ComponentEvent>>,
+    /// Boxed so PhysX can keep a pointer to it while the system moves around.
+    simulation_events: Box<SimulationEvents>,
+    simulation_callback: *mut PxSimulationEventCallback,
+    /// The settings the scene was last configured with.
+    config: PhysicsConfig,
+    debug_visualization: bool,
+    /// Last, so that it's released after everything created from it.
+    foundation: OwnedFoundation,
+}
+
+/// PhysX only allows one foundation per process, so it's released along with the system for
+/// another to be created.
+struct OwnedFoundation(Foundation);
+
+impl Drop for OwnedFoundation {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+/// Filled by the simulation event callback during `fetch_results`.
+#[derive(Default)]
+struct SimulationEvents {
+    contacts: Vec<RawContact>,
+    triggers: Vec<RawTrigger>,
+    /// Reused for extracting each pair's contact points.
+    scratch: Vec<PxContactPairPoint>,
 }
 
+struct RawTrigger {
+    kind: TriggerKind,
+    trigger: usize,
+    other: usize,
+}
+
+struct RawContact {
+    kind: ContactKind,
+    actors: [usize; 2],
+    points: Vec<ContactPoint>,
+}
+
+struct ColliderShape {
+    geometry: PhysicsGeometry,
+    local_pose: Mat4,
+    /// Triangle meshes and planes can't be simulated, so they always get a static actor.
+    static_only: bool,
+    /// Reports overlaps instead of colliding.
+    trigger: bool,
+}
+
+type Colliders<'a> = (ReadStorage<'a, BoxCollider>,
+                      ReadStorage<'a, SphereCollider>,
+                      ReadStorage<'a, CapsuleCollider>,
+                      ReadStorage<'a, ConvexMeshCollider>,
+                      ReadStorage<'a, StaticMeshCollider>,
+                      ReadStorage<'a, PlaneCollider>,
+                      ReadStorage<'a, TriggerVolume>);
+
 impl PhysicsSystem {
-    pub fn new() -> PhysicsSystem {
+    pub fn new(config: &PhysicsConfig) -> PhysicsSystem {
         let mut foundation = Foundation::new(PX_PHYSICS_VERSION);
         let mut physics = PhysicsBuilder::default()
-            .load_extensions(false)
+            .load_extensions(true)
             .build(&mut foundation);
 
-        let mut scene = physics.create_scene(
-            SceneBuilder::default()
-                .set_gravity(Vec3::new(0.0, -9.81, 0.0))
-                .set_simulation_threading(SimulationThreadType::Dedicated(8)),
-        );
+        // The scene is created raw so that actors can be removed from it again; `Scene` never lets go of them.
+        // Its description is built by hand too, as `SceneBuilder` can't ask for enhanced determinism.
+        let dispatcher = unsafe { phys_PxDefaultCpuDispatcherCreate(config.threads, std::ptr::null_mut()) };
+        let px_scene = unsafe {
+            let mut desc = PxSceneDesc_new(physics.get_tolerances_scale());
+            desc.cpuDispatcher = dispatcher as *mut PxCpuDispatcher;
+            desc.gravity = gl_to_px_v3(config.gravity);
+            // Keeps each group of touching bodies simulating the same way whatever else is in the scene,
+            // so replaying a snapshot isn't thrown off by bodies added or removed since.
+            desc.flags.mBits |= PxSceneFlag::eENABLE_ENHANCED_DETERMINISM;
+            // The default shader reads the filter data differently, so it mustn't see ours.
+            enable_custom_filter_shader(&mut desc, filter_shader, 0);
+            PxPhysics_createScene_mut(physics.get_raw_mut(), &desc)
+        };
+        let scene = Box::new(Scene::new(px_scene));
 
-        let material = physics.create_material(0.5, 0.5, 0.2);
+        let mut simulation_events = Box::new(SimulationEvents::default());
+        let simulation_callback = unsafe {
+            let callbacks = SimulationEventCallbackInfo {
+                collision_callback: Some(on_contact),
+                collision_user_data: simulation_events.as_mut() as *mut SimulationEvents as *mut c_void,
+                trigger_callback: Some(on_trigger),
+                trigger_user_data: simulation_events.as_mut() as *mut SimulationEvents as *mut c_void,
+                ..Default::default()
+            };
+            let simulation_callback = create_simulation_event_callbacks(&callbacks);
+            PxScene_setSimulationEventCallback_mut(px_scene, simulation_callback);
+            simulation_callback
+        };
 
-        let ground_plane = unsafe { physics.create_plane(Vec3::new(0.0, 1.0, 0.0), 0.0, material) };
-        scene.add_actor(ground_plane);
+        let cooking_params = unsafe { PxCookingParams_new(physics.get_tolerances_scale()) };
+        let cooking = Cooking::new(PX_PHYSICS_VERSION, &mut foundation, cooking_params);
 
-        return PhysicsSystem {
-            foundation,
+        let mut system = PhysicsSystem {
             physics,
             scene,
+            px_scene,
+            dispatcher,
+            cooking,
+            materials: HashMap::new(),
+            convex_meshes: HashMap::new(),
+            triangle_meshes: HashMap::new(),
+            bodies: HashMap::new(),
+            rigidbody_events: None,
+            degenerate_colliders: HashSet::new(),
+            joints: HashMap::new(),
+            joint_events: None,
+            controller_manager: unsafe { phys_PxCreateControllerManager(px_scene, false) },
+            controllers: HashMap::new(),
+            controller_events: None,
+            simulation_events,
+            simulation_callback,
+            config: config.clone(),
+            debug_visualization: false,
+            foundation: OwnedFoundation(foundation),
+        };
+
+        // Visualization is switched on and off as a whole through its scale.
+        for parameter in DEBUG_VISUALIZATIONS.iter() {
+            unsafe { PxScene_setVisualizationParameter_mut(px_scene, *parameter, 1.0) };
+        }
+        system.set_debug_visualization(false);
+        system.configure(config);
+        return system;
+    }
+
+    fn set_debug_visualization(&mut self, enabled: bool) {
+        let scale = if enabled { 1.0 } else { 0.0 };
+        unsafe { PxScene_setVisualizationParameter_mut(self.px_scene, PxVisualizationParameter::eSCALE, scale) };
+        self.debug_visualization = enabled;
+    }
+
+    /// Copies the lines, points and triangles PhysX visualized during the last step.
+    fn read_debug_lines(&self, lines: &mut Vec<DebugLine>) {
+        lines.clear();
+
+        unsafe {
+            let buffer = PxScene_getRenderBuffer_mut(self.px_scene);
+
+            let debug_lines = render_buffer_slice(PxRenderBuffer_getLines(buffer), PxRenderBuffer_getNbLines(buffer));
+            for line in debug_lines {
+                lines.push(DebugLine { start: px_to_gl_v3(line.pos0), end: px_to_gl_v3(line.pos1), color: debug_color(line.color0) });
+            }
+
+            let points = render_buffer_slice(PxRenderBuffer_getPoints(buffer), PxRenderBuffer_getNbPoints(buffer));
+            for point in points {
+                let position = px_to_gl_v3(point.pos);
+                for axis in [Vec3::unit_x(), Vec3::unit_y(), Vec3::unit_z()].iter() {
+                    let offset = *axis * DEBUG_POINT_SIZE;
+                    lines.push(DebugLine { start: position - offset, end: position + offset, color: debug_color(point.color) });
+                }
+            }
+
+            let triangles = render_buffer_slice(PxRenderBuffer_getTriangles(buffer), PxRenderBuffer_getNbTriangles(buffer));
+            for triangle in triangles {
+                let corners = [px_to_gl_v3(triangle.pos0), px_to_gl_v3(triangle.pos1), px_to_gl_v3(triangle.pos2)];
+                for i in 0..3 {
+                    lines.push(DebugLine { start: corners[i], end: corners[(i + 1) % 3], color: debug_color(triangle.color0) });
+                }
+            }
+        }
+    }
+
+    /// Applies everything in `config` that can change on a running scene.
+    fn configure(&mut self, config: &PhysicsConfig) {
+        unsafe {
+            PxScene_setGravity_mut(self.px_scene, &gl_to_px_v3(config.gravity));
+            PxScene_setBounceThresholdVelocity_mut(self.px_scene, config.bounce_threshold);
+        }
+        self.config = config.clone();
+
+        for body in self.bodies.values() {
+            let actor = body.0 as *mut PxActor;
+            if unsafe { PxActor_getType(actor) } == PxActorType::eRIGID_DYNAMIC {
+                configure_body(actor as *mut PxRigidDynamic, config);
+            }
+        }
+    }
+
+    fn add_actor(&mut self, queries: &mut PhysicsQueries, entity: Entity, actor: *mut PxRigidActor) -> BodyHandle {
+        unsafe {
+            PxScene_addActor_mut(self.px_scene, actor as *mut PxActor, null());
+        }
+
+        let handle = BodyHandle(actor as usize);
+        self.bodies.insert(entity.id(), handle);
+        queries.actors.insert(handle.0, entity);
+        handle
+    }
+
+    /// Moves an actor to a new pose, stopping it if it's dynamic.
+    fn teleport(&mut self, body: BodyHandle, pose: Mat4) {
+        let actor = body.0 as *mut PxRigidActor;
+        unsafe {
+            PxRigidActor_setGlobalPose_mut(actor, &gl_to_px_tf(pose), true);
+
+            if PxActor_getType(actor as *const PxActor) == PxActorType::eRIGID_DYNAMIC {
+                let zero = gl_to_px_v3(Vec3::zero());
+                PxRigidBody_setLinearVelocity_mut(actor as *mut PxRigidBody, &zero, true);
+                PxRigidBody_setAngularVelocity_mut(actor as *mut PxRigidBody, &zero, true);
+            }
+        }
+    }
+
+    /// Captures every dynamic and kinematic body, in entity order. Only poses, velocities and sleep
+    /// state are kept, not PhysX's contact caches or solver warm-starting; see `restore`.
+    pub fn snapshot(&self, world: &World) -> PhysicsSnapshot {
+        let queries = world.fetch::<PhysicsQueries>();
+        let mut bodies: Vec<BodySnapshot> = self.bodies.values().filter_map(|body| {
+            let actor = body.0 as *mut PxRigidActor;
+            let entity = *queries.actors.get(&body.0)?;
+            unsafe {
+                if PxActor_getType(actor as *const PxActor) != PxActorType::eRIGID_DYNAMIC {
+                    return None;
+                }
+
+                let dynamic = actor as *mut PxRigidDynamic;
+                let simulated = !is_kinematic(actor as *mut PxRigidBody);
+                let pose = PxRigidActor_getGlobalPose(actor);
+                Some(BodySnapshot {
+                    entity: entity.id(),
+                    generation: entity.gen().id(),
+                    rotation: Quat::from_xyzw(pose.q.x, pose.q.y, pose.q.z, pose.q.w),
+                    position: Vec3::new(pose.p.x, pose.p.y, pose.p.z),
+                    linear_velocity: if simulated { px_to_gl_v3(PxRigidBody_getLinearVelocity(actor as *mut PxRigidBody)) } else { Vec3::zero() },
+                    angular_velocity: if simulated { px_to_gl_v3(PxRigidBody_getAngularVelocity(actor as *mut PxRigidBody)) } else { Vec3::zero() },
+                    wake_counter: if simulated { PxRigidDynamic_getWakeCounter(dynamic) } else { 0.0 },
+                    sleeping: simulated && PxRigidDynamic_isSleeping(dynamic),
+                })
+            }
+        }).collect();
+        bodies.sort_by_key(|body| body.entity);
+
+        return PhysicsSnapshot { bodies };
+    }
+
+    /// Puts every body in `snapshot` back the way it was, along with its `Transform`. Bodies whose
+    /// entity has since been deleted are skipped. The bodies' contacts are dropped, so restoring a
+    /// snapshot always simulates the same way afterwards, but one taken mid-contact won't follow the
+    /// run it was taken from exactly.
+    pub fn restore(&mut self, world: &World, snapshot: &PhysicsSnapshot) {
+        let entities = world.entities();
+        let mut transforms = world.write_storage::<Transform>();
+        let mut previous = world.write_storage::<PreviousTransform>();
+        let mut sleeping = world.write_storage::<Sleeping>();
+
+        for body in &snapshot.bodies {
+            let entity = entities.entity(body.entity);
+            if !entities.is_alive(entity) || entity.gen().id() != body.generation {
+                continue;
+            }
+            let actor = match self.bodies.get(&body.entity) {
+                Some(handle) => handle.0 as *mut PxRigidActor,
+                None => continue,
+            };
+
+            let pose = PxTransform {
+                q: PxQuat { x: body.rotation.x(), y: body.rotation.y(), z: body.rotation.z(), w: body.rotation.w() },
+                p: gl_to_px_v3(body.position),
+            };
+
+            unsafe {
+                PxRigidActor_setGlobalPose_mut(actor, &pose, false);
+                // Drops the body's contacts, which would otherwise carry over from before restoring.
+                PxScene_resetFiltering_mut(self.px_scene, actor as *mut PxActor);
+
+                if !is_kinematic(actor as *mut PxRigidBody) {
+                    let dynamic = actor as *mut PxRigidDynamic;
+                    PxRigidBody_setLinearVelocity_mut(actor as *mut PxRigidBody, &gl_to_px_v3(body.linear_velocity), false);
+                    PxRigidBody_setAngularVelocity_mut(actor as *mut PxRigidBody, &gl_to_px_v3(body.angular_velocity), false);
+                    if body.sleeping {
+                        PxRigidDynamic_putToSleep_mut(dynamic);
+                    } else {
+                        PxRigidDynamic_setWakeCounter_mut(dynamic, body.wake_counter);
+                    }
+                }
+            }
+
+            if let Some(t) = transforms.get_mut(entity) {
+                let (_, scale) = split_scale(&t.0);
+                t.0 = Mat4::from_scale_rotation_translation(scale, body.rotation, body.position);
+                previous.insert(entity, PreviousTransform(t.0)).expect("entity is alive");
+            }
+            if body.sleeping {
+                sleeping.insert(entity, Sleeping).expect("entity is alive");
+            } else {
+                sleeping.remove(entity);
+            }
+        }
+    }
+
+    fn release_actor(&mut self, queries: &mut PhysicsQueries, index: Index) {
+        if let Some(handle) = self.bodies.remove(&index) {
+            // Their `Joint`s are created again once the body is, rather than being left attached to nothing.
+            self.joints.retain(|_, (px_joint, first, second)| {
+                let attached = *first == handle || *second == Some(handle);
+                if attached {
+                    release_joint(*px_joint, *first, *second);
+                }
+                !attached
+            });
+            queries.actors.remove(&handle.0);
+            release_rigid_actor(self.px_scene, handle);
+        }
+    }
+
+    fn material(&mut self, material: &PhysicsMaterial) -> *mut PxMaterial {
+        let key = [material.static_friction.to_bits(), material.dynamic_friction.to_bits(), material.restitution.to_bits()];
+        let physics = &mut self.physics;
+
+        *self.materials.entry(key).or_insert_with(|| {
+            physics.create_material(material.static_friction, material.dynamic_friction, material.restitution)
+        })
+    }
+
+    /// The PhysX controller behind an entity's `CharacterController`, created on first use and
+    /// resized whenever its shape changes.
+    fn controller(&mut self, queries: &mut PhysicsQueries, entity: Entity, character: &CharacterController, transform: &Transform, layer: CollisionLayer) -> *mut PxController {
+        if let Some(controller) = self.controllers.get(&entity.id()) {
+            resize_controller(*controller, character);
+            return *controller;
+        }
+
+        let controller = unsafe {
+            let desc = PxCapsuleControllerDesc_new_alloc();
+            let position = transform.0.w_axis();
+            (*desc).position = PxExtendedVec3 { x: position.x() as f64, y: position.y() as f64, z: position.z() as f64 };
+            (*desc).radius = character.radius;
+            (*desc).height = 2.0 * character.half_height;
+            (*desc).stepOffset = character.step_offset;
+            (*desc).slopeLimit = character.slope_limit.cos();
+            (*desc).material = self.material(&PhysicsMaterial::default());
+
+            let controller = PxControllerManager_createController_mut(self.controller_manager, desc as *mut PxControllerDesc);
+            PxCapsuleControllerDesc_delete(desc);
+            assert!(!controller.is_null(), "invalid character controller");
+            controller
+        };
+
+        let actor = unsafe { PxController_getActor(controller) } as *mut PxRigidActor;
+        set_collision_layer(actor, layer, false);
+        queries.actors.insert(actor as usize, entity);
+        self.controllers.insert(entity.id(), controller);
+        return controller;
+    }
+
+    fn release_controller(&mut self, queries: &mut PhysicsQueries, index: Index) {
+        if let Some(controller) = self.controllers.remove(&index) {
+            unsafe {
+                queries.actors.remove(&(PxController_getActor(controller) as usize));
+                PxController_release_mut(controller);
+            }
+        }
+    }
+
+    /// Creates the PhysX joint for a `Joint` along with the actors it joins, returning `None` while its
+    /// bodies don't exist yet.
+    fn create_joint(&mut self, joint: &Joint, rigidbody: &WriteStorage<Rigidbody>) -> Option<(*mut PxJoint, BodyHandle, Option<BodyHandle>)> {
+        let first_body = rigidbody.get(joint.first)?.handle?;
+        let second_body = match joint.second {
+            Some(second) => Some(rigidbody.get(second)?.handle?),
+            None => None,
+        };
+        let first = first_body.0 as *mut PxRigidActor;
+        let second = second_body.map_or(std::ptr::null_mut(), |body| body.0 as *mut PxRigidActor);
+
+        // PhysX wants the anchor relative to each body, or the world for a null actor.
+        let local_frame = |actor: *mut PxRigidActor| {
+            if actor.is_null() {
+                gl_to_px_tf(joint.anchor)
+            } else {
+                let pose = unsafe { px_to_gl_tf(PxRigidActor_getGlobalPose(actor)) };
+                gl_to_px_tf(pose.inverse() * joint.anchor)
+            }
+        };
+        let (frame0, frame1) = (local_frame(first), local_frame(second));
+        let physics = self.physics.get_raw_mut();
+
+        unsafe {
+            let px_joint = match joint.kind {
+                JointKind::Fixed => phys_PxFixedJointCreate(physics, first, &frame0, second, &frame1) as *mut PxJoint,
+                JointKind::Revolute(limit) => {
+                    let px_joint = phys_PxRevoluteJointCreate(physics, first, &frame0, second, &frame1);
+                    if let Some((lower, upper)) = limit {
+                        PxRevoluteJoint_setLimit_mut(px_joint, &PxJointAngularLimitPair_new(lower, upper, 0.1));
+                        PxRevoluteJoint_setRevoluteJointFlag_mut(px_joint, PxRevoluteJointFlag::eLIMIT_ENABLED, true);
+                    }
+                    px_joint as *mut PxJoint
+                }
+                JointKind::Spherical => phys_PxSphericalJointCreate(physics, first, &frame0, second, &frame1) as *mut PxJoint,
+                JointKind::Prismatic(limit) => {
+                    let px_joint = phys_PxPrismaticJointCreate(physics, first, &frame0, second, &frame1);
+                    if let Some((lower, upper)) = limit {
+                        let tolerances = self.physics.get_tolerances_scale();
+                        PxPrismaticJoint_setLimit_mut(px_joint, &PxJointLinearLimitPair_new(tolerances, lower, upper, -1.0));
+                        PxPrismaticJoint_setPrismaticJointFlag_mut(px_joint, PxPrismaticJointFlag::eLIMIT_ENABLED, true);
+                    }
+                    px_joint as *mut PxJoint
+                }
+                JointKind::Distance { min, max } => {
+                    let px_joint = phys_PxDistanceJointCreate(physics, first, &frame0, second, &frame1);
+                    PxDistanceJoint_setMinDistance_mut(px_joint, min);
+                    PxDistanceJoint_setMaxDistance_mut(px_joint, max);
+                    PxDistanceJoint_setDistanceJointFlag_mut(px_joint, PxDistanceJointFlag::eMIN_DISTANCE_ENABLED, true);
+                    PxDistanceJoint_setDistanceJointFlag_mut(px_joint, PxDistanceJointFlag::eMAX_DISTANCE_ENABLED, true);
+                    px_joint as *mut PxJoint
+                }
+            };
+            assert!(!px_joint.is_null(), "failed creating joint");
+            // Joints only draw their frames and limits when asked to, on top of the scene's parameters.
+            PxJoint_setConstraintFlag_mut(px_joint, PxConstraintFlag::eVISUALIZATION, true);
+            Some((px_joint, first_body, second_body))
+        }
+    }
+
+    /// Builds the PhysX geometry for an entity's collider, along with the shape's local pose.
+    fn collider_shape(&mut self, entity: Entity, scale: Vec3, (boxes, spheres, capsules, convex_meshes, static_meshes, planes, triggers): &Colliders) -> Option<ColliderShape> {
+        let scale = scale.abs();
+        let simple = |desc: ColliderDesc, local_pose: Mat4| ColliderShape {
+            geometry: PhysicsGeometry::from(&desc),
+            local_pose,
+            static_only: false,
+            trigger: false,
+        };
+
+        if let Some(c) = boxes.get(entity) {
+            let half_extents = c.0 * scale;
+            Some(simple(ColliderDesc::Box(half_extents.x(), half_extents.y(), half_extents.z()), Mat4::identity()))
+        } else if let Some(c) = spheres.get(entity) {
+            Some(simple(ColliderDesc::Sphere(c.0 * scale.max_element()), Mat4::identity()))
+        } else if let Some(c) = capsules.get(entity) {
+            let radius = c.radius * scale.x().max(scale.z());
+            // PhysX capsules extend along X, ours along Y.
+            let local_pose = Mat4::from_rotation_z(std::f32::consts::FRAC_PI_2);
+            Some(simple(ColliderDesc::Capsule(radius, 2.0 * c.half_height * scale.y()), local_pose))
+        } else if let Some(c) = convex_meshes.get(entity) {
+            let mesh = self.convex_mesh(c.0)?;
+            let geometry = unsafe {
+                let mesh_scale = PxMeshScale_new_2(&gl_to_px_v3(scale));
+                PxConvexMeshGeometry_new_1(mesh, &mesh_scale, PxConvexMeshGeometryFlags { mBits: 0 })
+            };
+            Some(ColliderShape {
+                geometry: PhysicsGeometry::new(Geometry::ConvexMesh(geometry)),
+                local_pose: Mat4::identity(),
+                static_only: false,
+                trigger: false,
+            })
+        } else if let Some(c) = static_meshes.get(entity) {
+            let mesh = self.triangle_mesh(c.0)?;
+            let geometry = unsafe {
+                let mesh_scale = PxMeshScale_new_2(&gl_to_px_v3(scale));
+                PxTriangleMeshGeometry_new_1(mesh, &mesh_scale, PxMeshGeometryFlags { mBits: 0 })
+            };
+            Some(ColliderShape {
+                geometry: PhysicsGeometry::new(Geometry::TriangleMesh(geometry)),
+                local_pose: Mat4::identity(),
+                static_only: true,
+                trigger: false,
+            })
+        } else if let Some(c) = planes.get(entity) {
+            if c.0.x().is_finite() && c.0.y().is_finite() {
+                // Finite planes are thin boxes whose top face lies on the plane.
+                let half_extents = Vec3::new(0.5 * c.0.x() * scale.x(), PLANE_HALF_THICKNESS, 0.5 * c.0.y() * scale.z());
+                let geometry = PhysicsGeometry::from(&ColliderDesc::Box(half_extents.x(), half_extents.y(), half_extents.z()));
+                Some(ColliderShape {
+                    geometry,
+                    local_pose: Mat4::from_translation(Vec3::new(0.0, -PLANE_HALF_THICKNESS, 0.0)),
+                    static_only: true,
+                    trigger: false,
+                })
+            } else {
+                // PhysX planes face along X, ours along Y.
+                Some(ColliderShape {
+                    geometry: PhysicsGeometry::new(Geometry::Plane(unsafe { PxPlaneGeometry_new() })),
+                    local_pose: Mat4::from_rotation_z(std::f32::consts::FRAC_PI_2),
+                    static_only: true,
+                    trigger: false,
+                })
+            }
+        } else if let Some(c) = triggers.get(entity) {
+            let desc = match c {
+                TriggerVolume::Box(half_extents) => {
+                    let half_extents = *half_extents * scale;
+                    ColliderDesc::Box(half_extents.x(), half_extents.y(), half_extents.z())
+                }
+                TriggerVolume::Sphere(radius) => ColliderDesc::Sphere(radius * scale.max_element()),
+            };
+            Some(ColliderShape {
+                geometry: PhysicsGeometry::from(&desc),
+                local_pose: Mat4::identity(),
+                static_only: false,
+                trigger: true,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Cooks the convex hull of an OBJ model, reusing the result for every collider sharing the data.
+    /// Models that fail to load or cook, e.g. empty ones, are reported once and leave their colliders
+    /// without a body.
+    fn convex_mesh(&mut self, data: &'static [u8]) -> Option<*mut PxConvexMesh> {
+        let cooking = &self.cooking;
+
+        let mesh = *self.convex_meshes.entry(data.as_ptr() as usize).or_insert_with(|| {
+            let positions = match loader::load_positions(data) {
+                Ok(positions) => positions,
+                Err(error) => {
+                    println!("Couldn't load convex mesh collider: {}", error);
+                    return std::ptr::null_mut();
+                }
+            };
+
+            unsafe {
+                let mut desc = PxConvexMeshDesc_new();
+                desc.points.count = positions.len() as u32;
+                desc.points.stride = std::mem::size_of::<[f32; 3]>() as u32;
+                desc.points.data = positions.as_ptr() as *const std::ffi::c_void;
+                desc.flags = PxConvexFlags { mBits: PxConvexFlag::eCOMPUTE_CONVEX as u16 };
+                // PhysX aborts rather than failing on a description it can't use, e.g. with no points.
+                if !PxConvexMeshDesc_isValid(&desc) {
+                    println!("Couldn't cook convex mesh collider: it has no usable points");
+                    return std::ptr::null_mut();
+                }
+
+                let mut result = PxConvexMeshCookingResult::eSUCCESS;
+                let mesh = PxCooking_createConvexMesh(
+                    cooking.get_raw(),
+                    &desc,
+                    PxPhysics_getPhysicsInsertionCallback_mut(phys_PxGetPhysics()),
+                    &mut result,
+                );
+                if mesh.is_null() {
+                    println!("Couldn't cook convex mesh collider ({})", result);
+                }
+                mesh
+            }
+        });
+
+        if mesh.is_null() { None } else { Some(mesh) }
+    }
+
+    /// Cooks the triangles of an OBJ model, reusing the result for every collider sharing the data.
+    /// Models that fail to load or cook, e.g. ones without faces, are reported once and leave their
+    /// colliders without a body.
+    fn triangle_mesh(&mut self, data: &'static [u8]) -> Option<*mut PxTriangleMesh> {
+        let cooking = &self.cooking;
+
+        let mesh = *self.triangle_meshes.entry(data.as_ptr() as usize).or_insert_with(|| {
+            let (positions, triangles) = match loader::load_triangles(data) {
+                Ok(loaded) => loaded,
+                Err(error) => {
+                    println!("Couldn't load static mesh collider: {}", error);
+                    return std::ptr::null_mut();
+                }
+            };
+
+            unsafe {
+                let mut desc = PxTriangleMeshDesc_new();
+                desc.points.count = positions.len() as u32;
+                desc.points.stride = std::mem::size_of::<[f32; 3]>() as u32;
+                desc.points.data = positions.as_ptr() as *const std::ffi::c_void;
+                desc.triangles.count = triangles.len() as u32;
+                desc.triangles.stride = std::mem::size_of::<[u32; 3]>() as u32;
+                desc.triangles.data = triangles.as_ptr() as *const std::ffi::c_void;
+                if !PxTriangleMeshDesc_isValid(&desc) {
+                    println!("Couldn't cook static mesh collider: it has no usable triangles");
+                    return std::ptr::null_mut();
+                }
+
+                let mut result = PxTriangleMeshCookingResult::eSUCCESS;
+                let mesh = PxCooking_createTriangleMesh(
+                    cooking.get_raw(),
+                    &desc,
+                    PxPhysics_getPhysicsInsertionCallback_mut(phys_PxGetPhysics()),
+                    &mut result,
+                );
+                if mesh.is_null() {
+                    println!("Couldn't cook static mesh collider ({})", result);
+                }
+                mesh
+            }
+        });
+
+        if mesh.is_null() { None } else { Some(mesh) }
+    }
+}
+
+/// Drops pairs whose `CollisionLayer`s don't match and asks for contact reports on the rest, only
+/// including persisting contacts and contact points when either side wants them.
+unsafe extern "C" fn filter_shader(info: *mut FilterShaderCallbackInfo) -> u16 {
+    let info = &mut *info;
+    let (first, second) = (&info.filterData0, &info.filterData1);
+    if first.word0 & second.word1 == 0 || second.word0 & first.word1 == 0 {
+        return PxFilterFlag::eSUPPRESS as u16;
+    }
+
+    (*info.pairFlags).mBits = if (info.attributes0 | info.attributes1) & PxFilterObjectFlag::eTRIGGER != 0 {
+        PxPairFlag::eTRIGGER_DEFAULT as u16
+    } else if (first.word2 | second.word2) & REPORT_CONTACTS != 0 {
+        (PxPairFlag::eCONTACT_DEFAULT
+            | PxPairFlag::eNOTIFY_TOUCH_FOUND
+            | PxPairFlag::eNOTIFY_TOUCH_PERSISTS
+            | PxPairFlag::eNOTIFY_TOUCH_LOST
+            | PxPairFlag::eNOTIFY_CONTACT_POINTS) as u16
+    } else {
+        (PxPairFlag::eCONTACT_DEFAULT
+            | PxPairFlag::eNOTIFY_TOUCH_FOUND
+            | PxPairFlag::eNOTIFY_TOUCH_LOST) as u16
+    };
+    PxFilterFlag::eDEFAULT as u16
+}
+
+unsafe extern "C" fn on_contact(user_data: *mut c_void, header: *const PxContactPairHeader, pairs: *const PxContactPair, count: u32) {
+    let events = &mut *(user_data as *mut SimulationEvents);
+    let header = &*header;
+
+    // Pairs losing touch because an actor was released; its entity is already gone.
+    if header.flags.mBits as u32 & (PxContactPairHeaderFlag::eREMOVED_ACTOR_0 | PxContactPairHeaderFlag::eREMOVED_ACTOR_1) != 0 {
+        return;
+    }
+
+    for pair in std::slice::from_raw_parts(pairs, count as usize) {
+        let flags = pair.events.mBits as u32;
+        let kind = if flags & PxPairFlag::eNOTIFY_TOUCH_FOUND != 0 {
+            ContactKind::Begin
+        } else if flags & PxPairFlag::eNOTIFY_TOUCH_PERSISTS != 0 {
+            ContactKind::Persist
+        } else if flags & PxPairFlag::eNOTIFY_TOUCH_LOST != 0 {
+            ContactKind::End
+        } else {
+            continue;
+        };
+
+        let points = if pair.contactCount > 0 {
+            let buffer = &mut events.scratch;
+            buffer.clear();
+            buffer.reserve(pair.contactCount as usize);
+            let extracted = PxContactPair_extractContacts(pair, buffer.as_mut_ptr(), pair.contactCount as u32);
+            buffer.set_len(extracted as usize);
+
+            buffer.iter().map(|point| ContactPoint {
+                position: px_to_gl_v3(point.position),
+                normal: px_to_gl_v3(point.normal),
+                impulse: px_to_gl_v3(point.impulse),
+                separation: point.separation,
+            }).collect()
+        } else {
+            Vec::new()
         };
+
+        events.contacts.push(RawContact {
+            kind,
+            actors: [header.actors[0] as usize, header.actors[1] as usize],
+            points,
+        });
+    }
+}
+
+unsafe extern "C" fn on_trigger(user_data: *mut c_void, pairs: *const PxTriggerPair, count: u32) {
+    let events = &mut *(user_data as *mut SimulationEvents);
+
+    for pair in std::slice::from_raw_parts(pairs, count as usize) {
+        // Overlaps ending because either side was released.
+        if pair.flags.mBits as u32 & (PxTriggerPairFlag::eREMOVED_SHAPE_TRIGGER | PxTriggerPairFlag::eREMOVED_SHAPE_OTHER) != 0 {
+            continue;
+        }
+
+        let kind = if pair.status & PxPairFlag::eNOTIFY_TOUCH_FOUND != 0 {
+            TriggerKind::Enter
+        } else if pair.status & PxPairFlag::eNOTIFY_TOUCH_LOST != 0 {
+            TriggerKind::Exit
+        } else {
+            continue;
+        };
+
+        events.triggers.push(RawTrigger {
+            kind,
+            trigger: pair.triggerActor as usize,
+            other: pair.otherActor as usize,
+        });
+    }
+}
+
+/// Writes a layer into the filter data of an actor's shape, which is read back by `filter_shader`
+/// and by the scene's built-in query filtering. Returns the shape.
+fn set_collision_layer(actor: *mut PxRigidActor, layer: CollisionLayer, report_contacts: bool) -> *mut PxShape {
+    unsafe {
+        let mut px_shape: *mut PxShape = std::ptr::null_mut();
+        PxRigidActor_getShapes(actor, &mut px_shape, 1, 0);
+        let reports = if report_contacts { REPORT_CONTACTS } else { 0 };
+        PxShape_setSimulationFilterData_mut(px_shape, &PxFilterData { word0: layer.group, word1: layer.mask, word2: reports, word3: 0 });
+        PxShape_setQueryFilterData_mut(px_shape, &PxFilterData { word0: layer.group, word1: 0, word2: 0, word3: 0 });
+        px_shape
+    }
+}
+
+fn is_valid_geometry(geometry: &PhysicsGeometry) -> bool {
+    unsafe {
+        match &geometry.geometry {
+            Geometry::Sphere(sphere) => PxSphereGeometry_isValid(sphere),
+            Geometry::Capsule(capsule) => PxCapsuleGeometry_isValid(capsule),
+            Geometry::Box(half_extents) => PxBoxGeometry_isValid(half_extents),
+            Geometry::ConvexMesh(mesh) => PxConvexMeshGeometry_isValid(mesh),
+            Geometry::TriangleMesh(mesh) => PxTriangleMeshGeometry_isValid(mesh),
+            Geometry::Plane(_) | Geometry::HeightField(_) => true,
+        }
+    }
+}
+
+fn release_rigid_actor(px_scene: *mut PxScene, body: BodyHandle) {
+    let actor = body.0 as *mut PxRigidActor;
+    unsafe {
+        // Frees the user data the wrapper attached when creating the actor.
+        RigidActor::from_ptr(actor).release();
+        PxScene_removeActor_mut(px_scene, actor as *mut PxActor, false);
+        PxActor_release_mut(actor as *mut PxActor);
+    }
+}
+
+/// PhysX hands back null rather than an empty array when there's nothing to draw.
+unsafe fn render_buffer_slice<'a, T>(items: *const T, count: u32) -> &'a [T] {
+    if items.is_null() {
+        &[]
+    } else {
+        std::slice::from_raw_parts(items, count as usize)
+    }
+}
+
+fn is_kinematic(body: *mut PxRigidBody) -> bool {
+    unsafe { PxRigidBody_getRigidBodyFlags(body).mBits as u32 & PxRigidBodyFlag::eKINEMATIC != 0 }
+}
+
+/// Unpacks a PhysX debug colour, which is ARGB.
+fn debug_color(color: u32) -> Vec3 {
+    let channel = |shift: u32| ((color >> shift) & 0xff) as f32 / 255.0;
+    Vec3::new(channel(16), channel(8), channel(0))
+}
+
+fn configure_body(body: *mut PxRigidDynamic, config: &PhysicsConfig) {
+    unsafe {
+        PxRigidDynamic_setSolverIterationCounts_mut(body, config.position_iterations, config.velocity_iterations);
+        PxRigidDynamic_setSleepThreshold_mut(body, config.sleep_threshold);
+    }
+}
+
+/// The PhysX body behind a `Rigidbody`, if it's dynamic and has been created. The actor itself is
+/// checked too, as only it knows for sure whether it can be cast.
+fn dynamic_body(rigidbody: &Rigidbody) -> Option<*mut PxRigidBody> {
+    match (rigidbody.handle, rigidbody.body_type) {
+        (Some(body), BodyType::Dynamic) if is_rigid_dynamic(body) => Some(body.0 as *mut PxRigidBody),
+        _ => None,
+    }
+}
+
+fn is_rigid_dynamic(body: BodyHandle) -> bool {
+    unsafe { PxActor_getType(body.0 as *const PxActor) == PxActorType::eRIGID_DYNAMIC }
+}
+
+/// Brings a capsule controller in line with its `CharacterController`, which can change at any time.
+fn resize_controller(controller: *mut PxController, character: &CharacterController) {
+    let capsule = controller as *mut PxCapsuleController;
+    let slope_limit = character.slope_limit.cos();
+    unsafe {
+        if PxCapsuleController_getRadius(capsule) != character.radius {
+            PxCapsuleController_setRadius_mut(capsule, character.radius);
+        }
+        if PxCapsuleController_getHeight(capsule) != 2.0 * character.half_height {
+            PxCapsuleController_setHeight_mut(capsule, 2.0 * character.half_height);
+        }
+        if PxController_getStepOffset(controller) != character.step_offset {
+            PxController_setStepOffset_mut(controller, character.step_offset);
+        }
+        if PxController_getSlopeLimit(controller) != slope_limit {
+            PxController_setSlopeLimit_mut(controller, slope_limit);
+        }
+    }
+}
+
+/// Releases a joint, waking the bodies it held so they don't stay hanging where it left them.
+fn release_joint(px_joint: *mut PxJoint, first: BodyHandle, second: Option<BodyHandle>) {
+    for body in std::iter::once(first).chain(second) {
+        if actor_body_type(body) == BodyType::Dynamic {
+            unsafe { PxRigidDynamic_wakeUp_mut(body.0 as *mut PxRigidDynamic) };
+        }
+    }
+    unsafe { PxJoint_release_mut(px_joint) };
+}
+
+/// The body type an actor was created as.
+fn actor_body_type(body: BodyHandle) -> BodyType {
+    if !is_rigid_dynamic(body) {
+        BodyType::Static
+    } else if is_kinematic(body.0 as *mut PxRigidBody) {
+        BodyType::Kinematic
+    } else {
+        BodyType::Dynamic
+    }
+}
+
+/// Splits a transform into the rigid pose PhysX can simulate and the scale it can't.
+fn split_scale(transform: &Mat4) -> (Mat4, Vec3) {
+    let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+    (Mat4::from_rotation_translation(rotation, translation), scale)
+}
+
+impl Drop for PhysicsSystem {
+    /// Releases everything the system created, leaving the foundation to go last.
+    fn drop(&mut self) {
+        unsafe {
+            for (_, (px_joint, ..)) in self.joints.drain() {
+                PxJoint_release_mut(px_joint);
+            }
+            // Takes its controllers with it.
+            PxControllerManager_release_mut(self.controller_manager);
+            self.controllers.clear();
+            for (_, body) in self.bodies.drain() {
+                release_rigid_actor(self.px_scene, body);
+            }
+
+            // Not `Scene::release`, which wants a contact callback we never gave it.
+            PxScene_release_mut(self.px_scene);
+            PxDefaultCpuDispatcher_release_mut(self.dispatcher);
+            destroy_simulation_event_callbacks(self.simulation_callback);
+
+            for (_, mesh) in self.convex_meshes.drain() {
+                if !mesh.is_null() {
+                    PxConvexMesh_release_mut(mesh);
+                }
+            }
+            for (_, mesh) in self.triangle_meshes.drain() {
+                if !mesh.is_null() {
+                    PxTriangleMesh_release_mut(mesh);
+                }
+            }
+            for (_, material) in self.materials.drain() {
+                PxMaterial_release_mut(material);
+            }
+            PxCooking_release_mut(self.cooking.get_raw_mut());
+        }
     }
 }
 
@@ -43,45 +917,803 @@ impl<'a> System<'a> for PhysicsSystem {
     type SystemData = (Read<'a, DeltaTime>,
                        Entities<'a>,
                        WriteStorage<'a, Transform>,
-                       ReadStorage<'a, BoxCollider>,
-                       WriteStorage<'a, Rigidbody>);
+                       Colliders<'a>,
+                       ReadStorage<'a, Density>,
+                       WriteStorage<'a, Rigidbody>,
+                       WriteStorage<'a, Teleport>,
+                       WriteStorage<'a, PreviousTransform>,
+                       ReadStorage<'a, ApplyForce>,
+                       WriteStorage<'a, ApplyImpulse>,
+                       WriteStorage<'a, SetLinearVelocity>,
+                       WriteStorage<'a, Velocity>,
+                       Write<'a, EventChannel<ContactEvent>>,
+                       Write<'a, EventChannel<TriggerEvent>>,
+                       WriteExpect<'a, PhysicsQueries>,
+                       ReadStorage<'a, Joint>,
+                       ReadStorage<'a, PhysicsMaterial>,
+                       ReadStorage<'a, CollisionLayer>,
+                       ReadStorage<'a, ContactReports>,
+                       WriteStorage<'a, CharacterController>,
+                       Read<'a, PhysicsConfig>,
+                       WriteStorage<'a, WakeUp>,
+                       WriteStorage<'a, Sleeping>,
+                       Write<'a, DebugLines>);
 
-    fn run(&mut self, (dt, entities, mut transform, collider, mut rigidbody): Self::SystemData) {
+    fn run(&mut self, (dt, entities, mut transform, colliders, density, mut rigidbody, mut teleport, mut previous, forces, mut impulses, mut set_velocities, mut velocities, mut contact_events, mut trigger_events, mut queries, joints, materials, layers, contact_reports, mut controllers, config, mut wake, mut sleeping, mut debug_lines): Self::SystemData) {
 
         let sw = Stopwatch::start_new();
 
+        if *config != self.config {
+            self.configure(&config);
+        }
+        if debug_lines.enabled != self.debug_visualization {
+            self.set_debug_visualization(debug_lines.enabled);
+        }
+
+        let events = self.joint_events.as_mut().expect("PhysicsSystem::setup wasn't called");
+        // Changed joints are released along with removed ones, and created again below.
+        for event in joints.channel().read(events) {
+            let index = match event {
+                ComponentEvent::Inserted(index) | ComponentEvent::Modified(index) | ComponentEvent::Removed(index) => index,
+            };
+            if let Some((px_joint, first, second)) = self.joints.remove(index) {
+                release_joint(px_joint, first, second);
+            }
+        }
+
+        let events = self.controller_events.as_mut().expect("PhysicsSystem::setup wasn't called");
+        let removed: Vec<Index> = controllers.channel().read(events).filter_map(|event| match event {
+            ComponentEvent::Removed(index) => Some(*index),
+            _ => None,
+        }).collect();
+        for index in removed {
+            self.release_controller(&mut queries, index);
+        }
+
+        let events = self.rigidbody_events.as_mut().expect("PhysicsSystem::setup wasn't called");
+        let changed: Vec<ComponentEvent> = rigidbody.channel().read(events).cloned().collect();
+        for event in changed {
+            match event {
+                ComponentEvent::Removed(index) => {
+                    self.release_actor(&mut queries, index);
+                    self.degenerate_colliders.remove(&index);
+                }
+                // A `Rigidbody` replaced on an entity that already has an actor, or whose body type
+                // changed, gets a new actor. Our own writes of the handle and type leave them matching.
+                ComponentEvent::Inserted(index) | ComponentEvent::Modified(index) => {
+                    let body = match self.bodies.get(&index) {
+                        Some(body) => *body,
+                        None => continue,
+                    };
+                    if let Some(r) = rigidbody.get_mut(entities.entity(index)) {
+                        if r.handle != Some(body) || r.body_type != actor_body_type(body) {
+                            self.release_actor(&mut queries, index);
+                            r.handle = None;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Bodies that haven't been created yet keep their request; they start out at their `Transform` anyway.
+        let mut teleported = Vec::new();
+        for (e, t, r, _) in (&entities, &transform, &rigidbody, &teleport).join() {
+            if let Some(body) = r.handle {
+                self.teleport(body, split_scale(&t.0).0);
+                teleported.push(e);
+            }
+        }
+        for (e, t, _, _) in (&entities, &transform, &controllers, &teleport).join() {
+            if let Some(controller) = self.controllers.get(&e.id()) {
+                let position = t.0.w_axis();
+                let position = PxExtendedVec3 { x: position.x() as f64, y: position.y() as f64, z: position.z() as f64 };
+                unsafe { PxController_setPosition_mut(*controller, &position) };
+                teleported.push(e);
+            }
+        }
+        for e in teleported {
+            teleport.remove(e);
+        }
+
+        for (r, _) in (&rigidbody, &wake).join() {
+            if let Some(body) = dynamic_body(r) {
+                unsafe { PxRigidDynamic_wakeUp_mut(body as *mut PxRigidDynamic) };
+            }
+        }
+        wake.clear();
+
+        for (e, t, r) in (&entities, &transform, &rigidbody).join() {
+            if let (Some(body), BodyType::Kinematic) = (r.handle, r.body_type) {
+                if !is_rigid_dynamic(body) {
+                    continue;
+                }
+                let (pose, scale) = split_scale(&t.0);
+                let current: Mat4 = unsafe { self.scene.get_rigid_actor_unchecked(&body) }.get_global_pose();
+                previous.insert(e, PreviousTransform(current * Mat4::from_scale(scale))).expect("entity is alive");
+
+                unsafe {
+                    PxRigidDynamic_setKinematicTarget_mut(body.0 as *mut PxRigidDynamic, &gl_to_px_tf(pose));
+                }
+            }
+        }
+
+        for (r, force) in (&rigidbody, &forces).join() {
+            if let Some(body) = dynamic_body(r) {
+                unsafe { PxRigidBody_addForce_mut(body, &gl_to_px_v3(force.0), PxForceMode::eFORCE, true) };
+            }
+        }
+
+        for (r, impulse) in (&rigidbody, &impulses).join() {
+            if let Some(body) = dynamic_body(r) {
+                unsafe { PxRigidBody_addForce_mut(body, &gl_to_px_v3(impulse.0), PxForceMode::eIMPULSE, true) };
+            }
+        }
+        impulses.clear();
+
+        for (r, velocity) in (&rigidbody, &set_velocities).join() {
+            if let Some(body) = dynamic_body(r) {
+                unsafe { PxRigidBody_setLinearVelocity_mut(body, &gl_to_px_v3(velocity.0), true) };
+            }
+        }
+        set_velocities.clear();
+
+        // Character controllers are moved directly rather than simulated.
+        for (e, c, t, l) in (&entities, &mut controllers, &mut transform, layers.maybe()).join() {
+            let layer = l.cloned().unwrap_or_default();
+            let controller = self.controller(&mut queries, e, c, t, layer);
+            previous.insert(e, PreviousTransform(t.0)).expect("entity is alive");
+
+            // The controller sweeps through the scene like a query, so it only hits shapes whose group
+            // overlaps its mask, the same way `PhysicsQueries` filters by layer.
+            let filter_data = PxFilterData { word0: layer.mask, word1: 0, word2: 0, word3: 0 };
+            unsafe {
+                let filters = PxControllerFilters_new(&filter_data, std::ptr::null_mut(), std::ptr::null_mut());
+                let flags = PxController_move_mut(controller, &gl_to_px_v3(c.velocity * dt.0), 0.001, dt.0, &filters, null());
+                c.grounded = flags.mBits as u32 & PxControllerCollisionFlag::eCOLLISION_DOWN != 0;
+
+                let position = *PxController_getPosition(controller);
+                let (scale, rotation, _) = t.0.to_scale_rotation_translation();
+                let translation = Vec3::new(position.x as f32, position.y as f32, position.z as f32);
+                t.0 = Mat4::from_scale_rotation_translation(scale, rotation, translation);
+            }
+        }
+
         self.scene.simulate(dt.0);
         self.scene.fetch_results(true).expect("error occured during simulation");
 
-        for (e, t, c, r) in (&entities, &mut transform, &collider, &mut rigidbody).join() {
-            let rb: &mut Rigidbody = r;
+        if self.debug_visualization {
+            self.read_debug_lines(&mut debug_lines.lines);
+        } else {
+            debug_lines.lines.clear();
+        }
+
+        let actors = &queries.actors;
+        let mut contacts: Vec<ContactEvent> = self.simulation_events.contacts.drain(..).filter_map(|contact| {
+            let first = *actors.get(&contact.actors[0])?;
+            let second = *actors.get(&contact.actors[1])?;
+            Some(ContactEvent { kind: contact.kind, entities: (first, second), points: contact.points })
+        }).collect();
+        contact_events.drain_vec_write(&mut contacts);
+
+        let mut triggers: Vec<TriggerEvent> = self.simulation_events.triggers.drain(..).filter_map(|trigger| {
+            Some(TriggerEvent { kind: trigger.kind, trigger: *actors.get(&trigger.trigger)?, other: *actors.get(&trigger.other)? })
+        }).collect();
+        trigger_events.drain_vec_write(&mut triggers);
 
-            match rb.0 {
+        let mut created = Vec::new();
+
+        // Joined immutably so that only newly created bodies get flagged as modified.
+        for (e, t, d, m, l, r) in (&entities, &mut transform, density.maybe(), materials.maybe(), layers.maybe(), &rigidbody).join() {
+            let (pose, scale) = split_scale(&t.0);
+
+            match r.handle {
+                // Static actors never move or sleep, whatever the `Rigidbody` says.
+                Some(body) if r.body_type == BodyType::Static || !is_rigid_dynamic(body) => {}
                 Some(body) => {
-                    let model: Mat4 = self.scene.get_rigid_actor(body).expect("Yeee").get_global_pose();
-                    t.0 = model;
+                    let asleep = match dynamic_body(r) {
+                        Some(body) => unsafe { PxRigidDynamic_isSleeping(body as *const PxRigidDynamic) },
+                        None => false,
+                    };
+                    if asleep && sleeping.contains(e) {
+                        continue;
+                    }
+
+                    // Kinematic bodies recorded where they were before being given their target.
+                    if r.body_type != BodyType::Kinematic {
+                        previous.insert(e, PreviousTransform(t.0)).expect("entity is alive");
+                    }
+                    let model: Mat4 = unsafe { self.scene.get_rigid_actor_unchecked(&body) }.get_global_pose();
+                    t.0 = model * Mat4::from_scale(scale);
+
+                    if let Some(body) = dynamic_body(r) {
+                        let velocity = unsafe {
+                            Velocity {
+                                linear: px_to_gl_v3(PxRigidBody_getLinearVelocity(body)),
+                                angular: px_to_gl_v3(PxRigidBody_getAngularVelocity(body)),
+                            }
+                        };
+                        velocities.insert(e, velocity).expect("entity is alive");
+                    }
+
+                    // Bodies that just fell asleep come to rest where they are rather than still interpolating.
+                    if asleep {
+                        previous.insert(e, PreviousTransform(t.0)).expect("entity is alive");
+                        sleeping.insert(e, Sleeping).expect("entity is alive");
+                    } else {
+                        sleeping.remove(e);
+                    }
                 }
                 None => {
-                    let material = self.physics.create_material(0.5, 0.5, 0.2);
-                    let sphere_geo = PhysicsGeometry::from(&ColliderDesc::Box(1.0, 1.0, 1.0));
-
-                    let mut sphere_actor = unsafe {
-                        self.physics.create_dynamic(
-                            t.0,
-                            sphere_geo.as_raw(), // todo: this should take the PhysicsGeometry straight.
-                            material,
-                            10.0,
-                            Mat4::identity(),
-                        )
+                    let shape = match self.collider_shape(e, scale, &colliders) {
+                        Some(shape) => shape,
+                        None => continue,
                     };
+                    // PhysX refuses to create actors with zero-sized shapes, e.g. from a zero scale.
+                    if !is_valid_geometry(&shape.geometry) {
+                        if self.degenerate_colliders.insert(e.id()) {
+                            println!("Not simulating {:?} until its collider has a size", e);
+                        }
+                        continue;
+                    }
+                    self.degenerate_colliders.remove(&e.id());
+                    let material = self.material(m.unwrap_or(&PhysicsMaterial::default()));
+
+                    let is_static = shape.static_only || r.body_type == BodyType::Static;
+                    let actor = if is_static {
+                        let mut actor = unsafe {
+                            self.physics.create_static(
+                                pose,
+                                shape.geometry.as_raw(),
+                                material,
+                                shape.local_pose,
+                            )
+                        };
+
+                        actor.get_raw_mut() as *mut PxRigidActor
+                    } else {
+                        let density = d.map_or(DEFAULT_DENSITY, |d| d.0);
+
+                        let mut actor = unsafe {
+                            self.physics.create_dynamic(
+                                pose,
+                                shape.geometry.as_raw(), // todo: this should take the PhysicsGeometry straight.
+                                material,
+                                density,
+                                shape.local_pose,
+                            )
+                        };
 
-                    sphere_actor.set_angular_damping(0.5);
-                    let sphere_handle = self.scene.add_dynamic(sphere_actor);
-                    rb.0 = Some(sphere_handle);
+                        actor.set_angular_damping(0.5);
+                        configure_body(actor.get_raw_mut(), &self.config);
+                        if r.body_type == BodyType::Kinematic {
+                            actor.set_rigid_body_flag(RigidBodyFlag::Kinematic, true);
+                        }
+                        actor.get_raw_mut() as *mut PxRigidActor
+                    };
+                    let px_shape = set_collision_layer(actor, l.cloned().unwrap_or_default(), contact_reports.contains(e));
+                    if shape.trigger {
+                        unsafe {
+                            PxShape_setFlag_mut(px_shape, PxShapeFlag::eSIMULATION_SHAPE, false);
+                            PxShape_setFlag_mut(px_shape, PxShapeFlag::eSCENE_QUERY_SHAPE, false);
+                            PxShape_setFlag_mut(px_shape, PxShapeFlag::eTRIGGER_SHAPE, true);
+                        }
+                    }
+                    // Reported here rather than while building the shape, so it's only said once per body.
+                    let (.., triggers) = &colliders;
+                    if !shape.trigger && triggers.contains(e) {
+                        println!("Ignoring the TriggerVolume of {:?}, which also has a collider", e);
+                    }
+                    created.push((e, self.add_actor(&mut queries, e, actor), is_static));
+                }
+            }
+        }
+
+        // Colliders that can only be static turn their body static, so it's never driven as a dynamic one.
+        for (e, handle, is_static) in created {
+            if let Some(r) = rigidbody.get_mut(e) {
+                r.handle = Some(handle);
+                if is_static {
+                    r.body_type = BodyType::Static;
+                }
+            }
+        }
+
+        for (e, joint) in (&entities, &joints).join() {
+            if !self.joints.contains_key(&e.id()) {
+                if let Some(created) = self.create_joint(joint, &rigidbody) {
+                    self.joints.insert(e.id(), created);
                 }
             }
         }
 
         println!("Physics took {}ms", sw.elapsed_ms());
     }
-}
\ No newline at end of file
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.rigidbody_events = Some(WriteStorage::<Rigidbody>::fetch(world).register_reader());
+        self.joint_events = Some(WriteStorage::<Joint>::fetch(world).register_reader());
+        self.controller_events = Some(WriteStorage::<CharacterController>::fetch(world).register_reader());
+        world.insert(PhysicsQueries::new(self.px_scene));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, MutexGuard};
+
+    use super::*;
+
+    /// Only one `PhysicsSystem` can exist at a time, so tests take turns.
+    static PHYSX: Mutex<()> = Mutex::new(());
+
+    /// Dropped in field order, so the system goes before the lock is released.
+    struct TestScene {
+        physics: PhysicsSystem,
+        world: World,
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    fn scene() -> TestScene {
+        let lock = PHYSX.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut world = World::new();
+        let mut physics = PhysicsSystem::new(&PhysicsConfig { threads: 0, ..PhysicsConfig::default() });
+        System::setup(&mut physics, &mut world);
+        world.insert(DeltaTime(1.0 / 60.0));
+        return TestScene { physics, world, _lock: lock };
+    }
+
+    fn step(scene: &mut TestScene, steps: usize) {
+        for _ in 0..steps {
+            scene.physics.run_now(&scene.world);
+            scene.world.maintain();
+        }
+    }
+
+    fn actor_count(scene: &TestScene) -> u32 {
+        let types = PxActorTypeFlags { mBits: (PxActorTypeFlag::eRIGID_STATIC | PxActorTypeFlag::eRIGID_DYNAMIC) as u16 };
+        return unsafe { PxScene_getNbActors(scene.physics.px_scene, types) };
+    }
+
+    /// Snapshots drop PhysX's contact state, so this only holds while nothing is touching yet.
+    #[test]
+    fn simulating_from_a_snapshot_taken_before_contact_reproduces_the_same_poses() {
+        let mut scene = scene();
+
+        scene.world.create_entity()
+            .with(Transform(Mat4::from_scale(Vec3::new(50.0, 1.0, 50.0))))
+            .with(PlaneCollider(Vec2::new(1.0, 1.0)))
+            .with(Rigidbody::new(BodyType::Static)).build();
+
+        // Stacked with gaps, so they're all still falling when the snapshot is taken and pile up afterwards.
+        for i in 0..4 {
+            let position = Vec3::new(0.2 * i as f32, 10.0 + 3.0 * i as f32, 0.0);
+            let builder = scene.world.create_entity().with(Transform(Mat4::from_translation(position)));
+            let builder = if i % 2 == 0 {
+                builder.with(BoxCollider(Vec3::new(1.0, 1.0, 1.0)))
+            } else {
+                builder.with(SphereCollider(1.0))
+            };
+            builder.with(Rigidbody::new(BodyType::Dynamic)).build();
+        }
+
+        step(&mut scene, 30);
+        let snapshot = scene.physics.snapshot(&scene.world);
+        assert_eq!(snapshot.bodies.len(), 4);
+
+        step(&mut scene, 120);
+        let expected = scene.physics.snapshot(&scene.world);
+
+        let restored = PhysicsSnapshot::from_bytes(&snapshot.to_bytes()).expect("snapshot round trips");
+        assert_eq!(restored, snapshot);
+        scene.physics.restore(&scene.world, &restored);
+        assert_eq!(scene.physics.snapshot(&scene.world), snapshot);
+
+        step(&mut scene, 120);
+        assert_eq!(scene.physics.snapshot(&scene.world), expected);
+    }
+
+    /// Restoring drops contacts, so replays from a snapshot taken mid-contact match each other rather
+    /// than the original run.
+    #[test]
+    fn restoring_a_snapshot_taken_mid_contact_always_simulates_the_same_way() {
+        let mut scene = scene();
+
+        scene.world.create_entity()
+            .with(Transform(Mat4::from_scale(Vec3::new(50.0, 1.0, 50.0))))
+            .with(PlaneCollider(Vec2::new(1.0, 1.0)))
+            .with(Rigidbody::new(BodyType::Static)).build();
+
+        let mut boxes = Vec::new();
+        for i in 0..4 {
+            let rotation = Quat::from_rotation_ypr(0.3 * i as f32, 0.2, 0.1 * i as f32);
+            let position = Vec3::new(0.3 * i as f32, 1.0 + 2.0 * i as f32, 0.0);
+            boxes.push(scene.world.create_entity()
+                .with(Transform(Mat4::from_rotation_translation(rotation, position)))
+                .with(BoxCollider(Vec3::new(1.0, 0.5, 1.0)))
+                .with(Rigidbody::new(BodyType::Dynamic)).build());
+        }
+
+        step(&mut scene, 60);
+        let snapshot = scene.physics.snapshot(&scene.world);
+        assert!(snapshot.bodies.iter().any(|body| !body.sleeping && body.linear_velocity != Vec3::zero()));
+        assert!(snapshot.bodies.iter().any(|body| body.rotation != Quat::identity()));
+
+        let restored = PhysicsSnapshot::from_bytes(&snapshot.to_bytes()).expect("snapshot round trips");
+        assert_eq!(restored, snapshot);
+        scene.physics.restore(&scene.world, &restored);
+        assert_eq!(scene.physics.snapshot(&scene.world), snapshot);
+        step(&mut scene, 120);
+        let expected = scene.physics.snapshot(&scene.world);
+
+        scene.physics.restore(&scene.world, &snapshot);
+        step(&mut scene, 120);
+        assert_eq!(scene.physics.snapshot(&scene.world), expected);
+
+        // A new entity reusing a deleted one's index isn't mistaken for it.
+        scene.world.delete_entity(boxes[0]).expect("box is alive");
+        step(&mut scene, 1);
+        let reused = scene.world.create_entity()
+            .with(Transform(Mat4::from_translation(Vec3::new(20.0, 0.5, 0.0))))
+            .with(BoxCollider(Vec3::new(0.5, 0.5, 0.5)))
+            .with(Rigidbody::new(BodyType::Dynamic)).build();
+        assert_eq!(reused.id(), boxes[0].id());
+        step(&mut scene, 1);
+        scene.physics.restore(&scene.world, &snapshot);
+        let position = scene.world.read_storage::<Transform>().get(reused).expect("transform is kept").0.w_axis();
+        assert!(position.x() > 19.0);
+    }
+
+    #[test]
+    fn zero_sized_colliders_are_skipped_until_they_have_a_size() {
+        let mut scene = scene();
+
+        let flat = scene.world.create_entity()
+            .with(Transform(Mat4::from_scale(Vec3::new(1.0, 0.0, 1.0))))
+            .with(BoxCollider(Vec3::new(1.0, 1.0, 1.0)))
+            .with(Rigidbody::new(BodyType::Dynamic)).build();
+        let empty = scene.world.create_entity()
+            .with(Transform(Mat4::identity()))
+            .with(SphereCollider(0.0))
+            .with(Rigidbody::new(BodyType::Static)).build();
+
+        step(&mut scene, 2);
+        {
+            let rigidbodies = scene.world.read_storage::<Rigidbody>();
+            assert!(rigidbodies.get(flat).expect("rigidbody is kept").handle.is_none());
+            assert!(rigidbodies.get(empty).expect("rigidbody is kept").handle.is_none());
+        }
+
+        scene.world.write_storage::<Transform>().insert(flat, Transform(Mat4::identity())).expect("entity is alive");
+        step(&mut scene, 1);
+        assert!(scene.world.read_storage::<Rigidbody>().get(flat).expect("rigidbody is kept").handle.is_some());
+    }
+
+    #[test]
+    fn convex_meshes_that_cant_be_cooked_leave_their_entity_without_a_body() {
+        let mut scene = scene();
+
+        let empty = scene.world.create_entity()
+            .with(Transform(Mat4::identity()))
+            .with(ConvexMeshCollider(b"o empty\n"))
+            .with(Rigidbody::new(BodyType::Dynamic)).build();
+
+        step(&mut scene, 2);
+        assert!(scene.world.read_storage::<Rigidbody>().get(empty).expect("rigidbody is kept").handle.is_none());
+    }
+
+    #[test]
+    fn triangle_meshes_that_cant_be_cooked_leave_their_entity_without_a_body() {
+        let mut scene = scene();
+
+        let faceless = scene.world.create_entity()
+            .with(Transform(Mat4::identity()))
+            .with(StaticMeshCollider(b"v 0 0 0\nv 1 0 0\nv 0 0 1\n"))
+            .with(Rigidbody::new(BodyType::Static)).build();
+
+        step(&mut scene, 2);
+        assert!(scene.world.read_storage::<Rigidbody>().get(faceless).expect("rigidbody is kept").handle.is_none());
+    }
+
+    #[test]
+    fn replacing_a_rigidbody_replaces_its_actor() {
+        let mut scene = scene();
+
+        let body = scene.world.create_entity()
+            .with(Transform(Mat4::identity()))
+            .with(BoxCollider(Vec3::new(1.0, 1.0, 1.0)))
+            .with(Rigidbody::new(BodyType::Dynamic)).build();
+
+        step(&mut scene, 2);
+        assert_eq!(actor_count(&scene), 1);
+
+        scene.world.write_storage::<Rigidbody>().insert(body, Rigidbody::new(BodyType::Dynamic)).expect("entity is alive");
+        step(&mut scene, 2);
+        assert_eq!(actor_count(&scene), 1);
+        assert_eq!(scene.world.read_storage::<Rigidbody>().get(body).expect("rigidbody is kept").handle, scene.physics.bodies.get(&body.id()).cloned());
+    }
+
+    #[test]
+    fn changing_the_body_type_recreates_the_actor() {
+        let mut scene = scene();
+
+        let body = scene.world.create_entity()
+            .with(Transform(Mat4::from_translation(Vec3::new(0.0, 10.0, 0.0))))
+            .with(BoxCollider(Vec3::new(1.0, 1.0, 1.0)))
+            .with(Rigidbody::new(BodyType::Static)).build();
+
+        step(&mut scene, 10);
+        let height = |scene: &TestScene| scene.world.read_storage::<Transform>().get(body).expect("transform is kept").0.w_axis().y();
+        assert_eq!(height(&scene), 10.0);
+
+        scene.world.write_storage::<Rigidbody>().get_mut(body).expect("rigidbody is kept").body_type = BodyType::Dynamic;
+        step(&mut scene, 10);
+        let fallen = height(&scene);
+        assert!(fallen < 10.0);
+
+        scene.world.write_storage::<Rigidbody>().get_mut(body).expect("rigidbody is kept").body_type = BodyType::Kinematic;
+        step(&mut scene, 10);
+        assert_eq!(actor_count(&scene), 1);
+        let handle = scene.world.read_storage::<Rigidbody>().get(body).expect("rigidbody is kept").handle.expect("body is recreated");
+        assert!(actor_body_type(handle) == BodyType::Kinematic);
+        assert!((height(&scene) - fallen).abs() < 0.5);
+    }
+
+    #[test]
+    fn bodies_falling_through_a_trigger_volume_enter_and_exit_it() {
+        let mut scene = scene();
+        let mut reader = scene.world.fetch_mut::<EventChannel<TriggerEvent>>().register_reader();
+
+        let volume = scene.world.create_entity()
+            .with(Transform(Mat4::identity()))
+            .with(TriggerVolume::Sphere(2.0))
+            .with(Rigidbody::new(BodyType::Static)).build();
+        let ball = scene.world.create_entity()
+            .with(Transform(Mat4::from_translation(Vec3::new(0.0, 5.0, 0.0))))
+            .with(SphereCollider(0.5))
+            .with(Rigidbody::new(BodyType::Dynamic)).build();
+
+        step(&mut scene, 120);
+        let events: Vec<(TriggerKind, Entity, Entity)> = scene.world.fetch::<EventChannel<TriggerEvent>>().read(&mut reader)
+            .map(|event| (event.kind, event.trigger, event.other))
+            .collect();
+        assert_eq!(events, vec![(TriggerKind::Enter, volume, ball), (TriggerKind::Exit, volume, ball)]);
+    }
+
+    #[test]
+    fn joints_follow_their_bodies_being_recreated() {
+        let mut scene = scene();
+
+        let position = Vec3::new(0.0, 5.0, 0.0);
+        let body = scene.world.create_entity()
+            .with(Transform(Mat4::from_translation(position)))
+            .with(BoxCollider(Vec3::new(0.5, 0.5, 0.5)))
+            .with(Rigidbody::new(BodyType::Dynamic)).build();
+        let joint = scene.world.create_entity()
+            .with(Joint { kind: JointKind::Fixed, first: body, second: None, anchor: Mat4::from_translation(position) })
+            .build();
+        let height = |scene: &TestScene| scene.world.read_storage::<Transform>().get(body).expect("transform is kept").0.w_axis().y();
+
+        step(&mut scene, 60);
+        assert!((height(&scene) - 5.0).abs() < 0.01);
+
+        // The old joint goes with the old actor, and a new one pins the new actor.
+        scene.world.write_storage::<Rigidbody>().insert(body, Rigidbody::new(BodyType::Dynamic)).expect("entity is alive");
+        step(&mut scene, 60);
+        let handle = scene.world.read_storage::<Rigidbody>().get(body).expect("rigidbody is kept").handle;
+        assert_eq!(scene.physics.joints.len(), 1);
+        assert_eq!(Some(scene.physics.joints[&joint.id()].1), handle);
+        assert!((height(&scene) - 5.0).abs() < 0.01);
+
+        scene.world.delete_entity(joint).expect("joint is alive");
+        step(&mut scene, 60);
+        assert!(scene.physics.joints.is_empty());
+        assert!(height(&scene) < 4.0);
+    }
+
+    #[test]
+    fn character_controllers_follow_teleports_and_changes_to_their_shape() {
+        let mut scene = scene();
+
+        let character = scene.world.create_entity()
+            .with(Transform(Mat4::identity()))
+            .with(CharacterController::new(0.5, 1.0)).build();
+        step(&mut scene, 2);
+
+        let target = Mat4::from_translation(Vec3::new(10.0, 5.0, 0.0));
+        scene.world.write_storage::<Transform>().insert(character, Transform(target)).expect("entity is alive");
+        scene.world.write_storage::<Teleport>().insert(character, Teleport).expect("entity is alive");
+        scene.world.write_storage::<CharacterController>().get_mut(character).expect("controller is kept").radius = 1.0;
+        step(&mut scene, 1);
+
+        assert!(!scene.world.read_storage::<Teleport>().contains(character));
+        assert!(scene.world.read_storage::<Transform>().get(character).expect("transform is kept").0.abs_diff_eq(target, 0.001));
+        let controller = scene.physics.controllers[&character.id()] as *mut PxCapsuleController;
+        assert_eq!(unsafe { PxCapsuleController_getRadius(controller) }, 1.0);
+    }
+
+    #[test]
+    fn impulses_and_set_velocities_show_up_in_the_velocity() {
+        let mut scene = scene();
+        scene.world.insert(PhysicsConfig { gravity: Vec3::zero(), threads: 0, ..PhysicsConfig::default() });
+
+        let pushed = scene.world.create_entity()
+            .with(Transform(Mat4::identity()))
+            .with(BoxCollider(Vec3::new(0.5, 0.5, 0.5)))
+            .with(Rigidbody::new(BodyType::Dynamic)).build();
+        let thrown = scene.world.create_entity()
+            .with(Transform(Mat4::from_translation(Vec3::new(10.0, 0.0, 0.0))))
+            .with(BoxCollider(Vec3::new(0.5, 0.5, 0.5)))
+            .with(Rigidbody::new(BodyType::Dynamic)).build();
+        step(&mut scene, 1);
+
+        scene.world.write_storage::<ApplyImpulse>().insert(pushed, ApplyImpulse(Vec3::new(0.0, 0.0, 10.0))).expect("entity is alive");
+        scene.world.write_storage::<SetLinearVelocity>().insert(thrown, SetLinearVelocity(Vec3::new(0.0, 3.0, 0.0))).expect("entity is alive");
+        step(&mut scene, 1);
+
+        assert!(!scene.world.read_storage::<ApplyImpulse>().contains(pushed));
+        let velocities = scene.world.read_storage::<Velocity>();
+        let pushed_velocity = velocities.get(pushed).expect("dynamic bodies have a velocity").linear;
+        assert!(pushed_velocity.z() > 0.0 && pushed_velocity.x() == 0.0 && pushed_velocity.y() == 0.0);
+        assert!(velocities.get(thrown).expect("dynamic bodies have a velocity").linear.abs_diff_eq(Vec3::new(0.0, 3.0, 0.0), 0.001));
+    }
+
+    #[test]
+    fn landing_begins_a_contact_with_points_only_for_contact_reports() {
+        let mut scene = scene();
+        let mut reader = scene.world.fetch_mut::<EventChannel<ContactEvent>>().register_reader();
+
+        let ground = scene.world.create_entity()
+            .with(Transform(Mat4::from_scale(Vec3::new(50.0, 1.0, 50.0))))
+            .with(PlaneCollider(Vec2::new(1.0, 1.0)))
+            .with(Rigidbody::new(BodyType::Static)).build();
+        let quiet = scene.world.create_entity()
+            .with(Transform(Mat4::from_translation(Vec3::new(-5.0, 2.0, 0.0))))
+            .with(BoxCollider(Vec3::new(0.5, 0.5, 0.5)))
+            .with(Rigidbody::new(BodyType::Dynamic)).build();
+        let reported = scene.world.create_entity()
+            .with(Transform(Mat4::from_translation(Vec3::new(5.0, 2.0, 0.0))))
+            .with(BoxCollider(Vec3::new(0.5, 0.5, 0.5)))
+            .with(ContactReports)
+            .with(Rigidbody::new(BodyType::Dynamic)).build();
+
+        step(&mut scene, 60);
+        let channel = scene.world.fetch::<EventChannel<ContactEvent>>();
+        let events: Vec<&ContactEvent> = channel.read(&mut reader).collect();
+        let begun = |entity: Entity| events.iter().find(|event| {
+            event.kind == ContactKind::Begin && (event.entities == (entity, ground) || event.entities == (ground, entity))
+        }).expect("landing begins a contact");
+
+        assert!(begun(quiet).points.is_empty());
+        let points = &begun(reported).points;
+        assert!(!points.is_empty());
+        assert!(points.iter().all(|point| point.position.y().abs() < 0.1 && point.normal.y().abs() > 0.99));
+        assert!(events.iter().any(|event| event.kind == ContactKind::Persist && (event.entities.0 == reported || event.entities.1 == reported)));
+        assert!(!events.iter().any(|event| event.kind == ContactKind::Persist && (event.entities.0 == quiet || event.entities.1 == quiet)));
+    }
+
+    #[test]
+    fn queries_hit_the_entities_in_the_way_on_the_asked_for_layers() {
+        let mut scene = scene();
+
+        let near = scene.world.create_entity()
+            .with(Transform(Mat4::identity()))
+            .with(BoxCollider(Vec3::new(1.0, 1.0, 1.0)))
+            .with(CollisionLayer { group: 1 << 1, mask: !0 })
+            .with(Rigidbody::new(BodyType::Static)).build();
+        let far = scene.world.create_entity()
+            .with(Transform(Mat4::from_translation(Vec3::new(10.0, 0.0, 0.0))))
+            .with(BoxCollider(Vec3::new(1.0, 1.0, 1.0)))
+            .with(Rigidbody::new(BodyType::Static)).build();
+        step(&mut scene, 1);
+
+        let queries = scene.world.fetch::<PhysicsQueries>();
+        let origin = Vec3::new(-10.0, 0.0, 0.0);
+        let hit = queries.raycast(origin, Vec3::unit_x(), 100.0, !0).expect("ray hits the near box");
+        assert_eq!(hit.entity, near);
+        assert!((hit.distance - 9.0).abs() < 0.001);
+        assert!(hit.position.abs_diff_eq(Vec3::new(-1.0, 0.0, 0.0), 0.001));
+        assert!(hit.normal.abs_diff_eq(-Vec3::unit_x(), 0.001));
+
+        assert_eq!(queries.raycast(origin, Vec3::unit_x(), 100.0, 1).expect("ray hits the far box").entity, far);
+        assert!(queries.raycast(origin, Vec3::unit_x(), 5.0, !0).is_none());
+        assert_eq!(queries.sweep_sphere(0.5, origin, Vec3::unit_x(), 100.0, 1).expect("sphere hits the far box").entity, far);
+        assert_eq!(queries.sweep_box(Vec3::splat(0.5), Mat4::from_translation(origin), Vec3::unit_x(), 100.0, !0).expect("box hits the near box").entity, near);
+
+        assert_eq!(queries.overlap_sphere(1.0, Vec3::new(10.0, 1.5, 0.0), !0), vec![far]);
+        assert_eq!(queries.overlap_box(Vec3::new(6.0, 0.5, 0.5), Mat4::from_translation(Vec3::new(5.0, 0.0, 0.0)), !0).len(), 2);
+        assert_eq!(queries.overlap_box(Vec3::new(6.0, 0.5, 0.5), Mat4::from_translation(Vec3::new(5.0, 0.0, 0.0)), 1 << 1), vec![near]);
+    }
+
+    #[test]
+    fn bouncy_materials_bounce_higher() {
+        let mut scene = scene();
+
+        scene.world.create_entity()
+            .with(Transform(Mat4::from_scale(Vec3::new(50.0, 1.0, 50.0))))
+            .with(PlaneCollider(Vec2::new(1.0, 1.0)))
+            .with(Rigidbody::new(BodyType::Static)).build();
+        let drop_ball = |scene: &mut TestScene, x: f32, restitution: f32| scene.world.create_entity()
+            .with(Transform(Mat4::from_translation(Vec3::new(x, 5.0, 0.0))))
+            .with(SphereCollider(0.5))
+            .with(PhysicsMaterial { restitution, ..PhysicsMaterial::default() })
+            .with(Rigidbody::new(BodyType::Dynamic)).build();
+        let bouncy = drop_ball(&mut scene, -5.0, 1.0);
+        let dull = drop_ball(&mut scene, 5.0, 0.0);
+
+        // Highest each gets after first landing, about a second in.
+        let mut highest = (0.0f32, 0.0f32);
+        step(&mut scene, 65);
+        for _ in 0..30 {
+            step(&mut scene, 1);
+            let transforms = scene.world.read_storage::<Transform>();
+            let height = |entity: Entity| transforms.get(entity).expect("transform is kept").0.w_axis().y();
+            highest = (highest.0.max(height(bouncy)), highest.1.max(height(dull)));
+        }
+        assert!(highest.0 > 2.0, "bouncy ball only got back up to {}", highest.0);
+        assert!(highest.1 < 1.0, "dull ball bounced up to {}", highest.1);
+    }
+
+    #[test]
+    fn bodies_only_collide_with_layers_in_their_mask() {
+        let mut scene = scene();
+
+        let ghost_layer = CollisionLayer { group: 1 << 1, mask: !(1 << 1) };
+        scene.world.create_entity()
+            .with(Transform(Mat4::from_scale(Vec3::new(50.0, 1.0, 50.0))))
+            .with(PlaneCollider(Vec2::new(1.0, 1.0)))
+            .with(Rigidbody::new(BodyType::Static)).build();
+        // A ghost ledge only ghosts fall through; everything lands on the floor.
+        scene.world.create_entity()
+            .with(Transform(Mat4::from_translation(Vec3::new(0.0, 2.0, 0.0))))
+            .with(BoxCollider(Vec3::new(10.0, 0.5, 10.0)))
+            .with(ghost_layer)
+            .with(Rigidbody::new(BodyType::Static)).build();
+        let falling = |scene: &mut TestScene, x: f32| scene.world.create_entity()
+            .with(Transform(Mat4::from_translation(Vec3::new(x, 6.0, 0.0))))
+            .with(BoxCollider(Vec3::new(0.5, 0.5, 0.5)))
+            .with(Rigidbody::new(BodyType::Dynamic)).build();
+        let solid = falling(&mut scene, -5.0);
+        let ghost = falling(&mut scene, 5.0);
+        scene.world.write_storage::<CollisionLayer>().insert(ghost, ghost_layer).expect("entity is alive");
+
+        step(&mut scene, 120);
+        let transforms = scene.world.read_storage::<Transform>();
+        let height = |entity: Entity| transforms.get(entity).expect("transform is kept").0.w_axis().y();
+        assert!((height(solid) - 3.0).abs() < 0.1, "solid box is at {}", height(solid));
+        assert!((height(ghost) - 0.5).abs() < 0.1, "ghost box is at {}", height(ghost));
+    }
+
+    #[test]
+    fn static_only_colliders_make_their_body_static() {
+        let mut scene = scene();
+
+        let plane = scene.world.create_entity()
+            .with(Transform(Mat4::identity()))
+            .with(PlaneCollider(Vec2::new(10.0, 10.0)))
+            .with(ApplyForce(Vec3::new(0.0, 100.0, 0.0)))
+            .with(Rigidbody::new(BodyType::Dynamic)).build();
+
+        let ramp = scene.world.create_entity()
+            .with(Transform(Mat4::from_translation(Vec3::new(5.0, 1.0, 0.0))))
+            .with(StaticMeshCollider(include_bytes!("../resources/models/cube.obj")))
+            .with(Rigidbody::new(BodyType::Kinematic)).build();
+
+        step(&mut scene, 10);
+
+        // Moving the kinematic ramp's transform mustn't hand its static actor a kinematic target.
+        scene.world.write_storage::<Transform>().insert(ramp, Transform(Mat4::identity())).expect("entity is alive");
+        step(&mut scene, 10);
+
+        let rigidbodies = scene.world.read_storage::<Rigidbody>();
+        let transforms = scene.world.read_storage::<Transform>();
+        let velocities = scene.world.read_storage::<Velocity>();
+        for entity in [plane, ramp].iter() {
+            let rigidbody = rigidbodies.get(*entity).expect("rigidbody is kept");
+            assert!(rigidbody.handle.is_some());
+            assert!(rigidbody.body_type == BodyType::Static);
+            assert!(velocities.get(*entity).is_none());
+            assert_eq!(transforms.get(*entity).expect("transform is kept").0, Mat4::identity());
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::ptr::{null, null_mut};
+
+use glam::*;
+use physx::prelude::*;
+use physx::transform::{gl_to_px_tf, gl_to_px_v3, px_to_gl_v3};
+use physx_sys::*;
+use specs::Entity;
+
+/// The most entities a single overlap query reports.
+const MAX_OVERLAPS: usize = 256;
+
+/// Raycasts, sweeps and overlaps against the physics scene. Inserted as a resource by `PhysicsSystem::setup`.
+pub struct PhysicsQueries {
+    scene: *mut PxScene,
+    /// Every actor in the scene, by its PhysX pointer, kept up to date by `PhysicsSystem`.
+    pub(crate) actors: HashMap<usize, Entity>,
+}
+
+// The scene is only simulated while `PhysicsSystem` holds this resource mutably, so shared
+// access never overlaps a step.
+unsafe impl Send for PhysicsQueries {}
+unsafe impl Sync for PhysicsQueries {}
+
+pub struct QueryHit {
+    pub entity: Entity,
+    pub position: Vec3,
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+impl PhysicsQueries {
+    pub fn new(scene: *mut PxScene) -> PhysicsQueries {
+        return PhysicsQueries { scene, actors: HashMap::new() };
+    }
+
+    /// The closest entity along a ray.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_distance: f32, layers: u32) -> Option<QueryHit> {
+        unsafe {
+            let mut hit = PxRaycastHit_new();
+            let filter_data = layer_filter(layers);
+
+            let hit_anything = PxSceneQueryExt_raycastSingle_mut(
+                self.scene,
+                &gl_to_px_v3(origin),
+                &gl_to_px_v3(direction.normalize()),
+                max_distance,
+                PxSceneQueryFlags { mBits: PxHitFlag::eDEFAULT as u16 },
+                &mut hit,
+                &filter_data as *const PxQueryFilterData as *const PxSceneQueryFilterData,
+                null_mut(),
+                null(),
+            );
+
+            if !hit_anything {
+                return None;
+            }
+
+            Some(QueryHit {
+                entity: *self.actors.get(&(hit.actor as usize))?,
+                position: px_to_gl_v3(hit.position),
+                normal: px_to_gl_v3(hit.normal),
+                distance: hit.distance,
+            })
+        }
+    }
+
+    /// The first entity a sphere moving from `origin` would hit.
+    pub fn sweep_sphere(&self, radius: f32, origin: Vec3, direction: Vec3, max_distance: f32, layers: u32) -> Option<QueryHit> {
+        let geometry = PhysicsGeometry::from(&ColliderDesc::Sphere(radius));
+        self.sweep(&geometry, Mat4::from_translation(origin), direction, max_distance, layers)
+    }
+
+    /// The first entity a box at `pose` would hit.
+    pub fn sweep_box(&self, half_extents: Vec3, pose: Mat4, direction: Vec3, max_distance: f32, layers: u32) -> Option<QueryHit> {
+        let geometry = PhysicsGeometry::from(&ColliderDesc::Box(half_extents.x(), half_extents.y(), half_extents.z()));
+        self.sweep(&geometry, pose, direction, max_distance, layers)
+    }
+
+    /// Every entity touching a sphere.
+    pub fn overlap_sphere(&self, radius: f32, position: Vec3, layers: u32) -> Vec<Entity> {
+        let geometry = PhysicsGeometry::from(&ColliderDesc::Sphere(radius));
+        self.overlap(&geometry, Mat4::from_translation(position), layers)
+    }
+
+    /// Every entity touching a box at `pose`.
+    pub fn overlap_box(&self, half_extents: Vec3, pose: Mat4, layers: u32) -> Vec<Entity> {
+        let geometry = PhysicsGeometry::from(&ColliderDesc::Box(half_extents.x(), half_extents.y(), half_extents.z()));
+        self.overlap(&geometry, pose, layers)
+    }
+
+    fn sweep(&self, geometry: &PhysicsGeometry, pose: Mat4, direction: Vec3, max_distance: f32, layers: u32) -> Option<QueryHit> {
+        unsafe {
+            let mut hit = PxSweepHit_new();
+            let filter_data = layer_filter(layers);
+
+            let hit_anything = PxSceneQueryExt_sweepSingle_mut(
+                self.scene,
+                geometry.as_raw(),
+                &gl_to_px_tf(pose),
+                &gl_to_px_v3(direction.normalize()),
+                max_distance,
+                PxSceneQueryFlags { mBits: PxHitFlag::eDEFAULT as u16 },
+                &mut hit,
+                &filter_data as *const PxQueryFilterData as *const PxSceneQueryFilterData,
+                null_mut(),
+                null(),
+                0.0,
+            );
+
+            if !hit_anything {
+                return None;
+            }
+
+            Some(QueryHit {
+                entity: *self.actors.get(&(hit.actor as usize))?,
+                position: px_to_gl_v3(hit.position),
+                normal: px_to_gl_v3(hit.normal),
+                distance: hit.distance,
+            })
+        }
+    }
+
+    fn overlap(&self, geometry: &PhysicsGeometry, pose: Mat4, layers: u32) -> Vec<Entity> {
+        unsafe {
+            let mut hits: Vec<PxOverlapHit> = Vec::with_capacity(MAX_OVERLAPS);
+            let filter_data = layer_filter(layers);
+
+            let count = PxSceneQueryExt_overlapMultiple_mut(
+                self.scene,
+                geometry.as_raw(),
+                &gl_to_px_tf(pose),
+                hits.as_mut_ptr(),
+                MAX_OVERLAPS as u32,
+                &filter_data as *const PxQueryFilterData as *const PxSceneQueryFilterData,
+                null_mut(),
+            );
+
+            // A negative count means the buffer overflowed, in which case it's full.
+            hits.set_len(if count < 0 { MAX_OVERLAPS } else { count as usize });
+
+            hits.iter().filter_map(|hit| self.actors.get(&(hit.actor as usize)).copied()).collect()
+        }
+    }
+}
+
+/// Only hits colliders whose `CollisionLayer` group overlaps `layers`.
+fn layer_filter(layers: u32) -> PxQueryFilterData {
+    let mut filter_data = unsafe { PxQueryFilterData_new() };
+    filter_data.data.word0 = layers;
+    filter_data
+}
@@ -1,19 +1,26 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::iter::Map;
 
 use glam::*;
 use glium::{BackfaceCullingMode, DepthTest, PolygonMode, Program, Surface, VertexBuffer};
+use glium::index::IndexBuffer;
 use glium::vertex::VertexBufferAny;
 use imgui::*;
 use rand::Rng;
 use specs::*;
+use specs::shrev::EventChannel;
 use stopwatch::Stopwatch;
 use winit::event_loop::ControlFlow;
 use winit::platform::desktop::EventLoopExtDesktop;
 
-use crate::{colors, loader};
+use crate::colors;
 use crate::common::*;
+use crate::config::PhysicsConfig;
+use crate::meshes::MeshRegistry;
+use crate::textures::TextureRegistry;
+use crate::queries::{PhysicsQueries, QueryHit};
 use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{MipmapsOption, Texture2d};
 
 pub struct RenderingSystem<'a> {
     system: crate::support::System,
@@ -22,18 +29,29 @@ pub struct RenderingSystem<'a> {
     instanced_diffuse_program: Program,
     shadow_diffuse_program: Program,
     instanced_shadow_diffuse_program: Program,
+    textured_shadow_diffuse_program: Program,
     shadow_program: Program,
     instanced_shadow_program: Program,
     image_program: Program,
     solid_program: Program,
-    cube: VertexBufferAny,
-    plane: VertexBufferAny,
-    sphere: VertexBufferAny,
+    lines_program: Program,
+    /// Uploaded models, indexed like the `MeshRegistry`.
+    meshes: Vec<GpuMesh>,
+    /// Uploaded images, indexed like the `TextureRegistry`.
+    textures: Vec<Texture2d>,
     shadow_texture: glium::texture::DepthTexture2d,
     shadow_draw_params: glium::draw_parameters::DrawParameters<'a>,
     shadow_projection: Mat4,
     shadow_view: Mat4,
     light_loc: Vec3,
+    /// Whether WASD walks the character and the camera follows it, rather than flying the camera.
+    follow_character: bool,
+    contact_events: Option<ReaderId<ContactEvent>>,
+    /// The contact point with the largest impulse reported so far, and the entities it was between.
+    hardest_impact: Option<(ContactPoint, (Entity, Entity))>,
+    trigger_events: Option<ReaderId<TriggerEvent>>,
+    /// What's inside each trigger volume, going by its events.
+    trigger_occupants: HashMap<Entity, HashSet<Entity>>,
 }
 
 impl<'a> RenderingSystem<'a> {
@@ -68,6 +86,13 @@ impl<'a> RenderingSystem<'a> {
             None,
         ).unwrap();
 
+        let textured_shadow_diffuse_program = glium::Program::from_source(
+            &system.display,
+            include_str!("../resources/shaders/diffuse-shadow-textured-instanced.vert.glsl"),
+            include_str!("../resources/shaders/diffuse-shadow-textured-instanced.frag.glsl"),
+            None,
+        ).unwrap();
+
         let shadow_program = glium::Program::from_source(
             &system.display,
             include_str!("../resources/shaders/shadow.vert.glsl"),
@@ -96,6 +121,13 @@ impl<'a> RenderingSystem<'a> {
             None,
         ).unwrap();
 
+        let lines_program = glium::Program::from_source(
+            &system.display,
+            include_str!("../resources/shaders/lines.vert.glsl"),
+            include_str!("../resources/shaders/lines.frag.glsl"),
+            None,
+        ).unwrap();
+
         let light_loc = [0.4, 1.0, 0.7];
         let shadow_map_size = 10000;
         let shadow_texture = glium::texture::DepthTexture2d::empty(&system.display, shadow_map_size, shadow_map_size).unwrap();
@@ -111,10 +143,6 @@ impl<'a> RenderingSystem<'a> {
         };
         shadow_draw_params.backface_culling = glium::BackfaceCullingMode::CullCounterClockwise;
 
-        let cube = loader::load_wavefront(&system.display, include_bytes!("../resources/models/cube.obj"));
-        let plane = loader::load_wavefront(&system.display, include_bytes!("../resources/models/plane.obj"));
-        let sphere = loader::load_wavefront(&system.display, include_bytes!("../resources/models/sphere.obj"));
-
         RenderingSystem {
             system,
             camera: crate::camera::Camera::new(Vec3::zero()),
@@ -122,87 +150,111 @@ impl<'a> RenderingSystem<'a> {
             instanced_diffuse_program,
             shadow_diffuse_program,
             instanced_shadow_diffuse_program,
+            textured_shadow_diffuse_program,
             shadow_program,
             instanced_shadow_program,
             image_program,
             solid_program,
-            cube,
-            plane,
-            sphere,
+            lines_program,
+            meshes: Vec::new(),
+            textures: Vec::new(),
             shadow_texture,
             shadow_draw_params,
             shadow_projection,
             shadow_view,
             light_loc: light_loc.into(),
+            follow_character: true,
+            contact_events: None,
+            hardest_impact: None,
+            trigger_events: None,
+            trigger_occupants: HashMap::new(),
         }
     }
 
-    fn draw_mesh_shadows<'b>(&self, transforms: &ReadStorage<'b, Transform>, mesh_renderers: &ReadStorage<'b, MeshRenderer>) {
-        let mut shadow_target = glium::framebuffer::SimpleFrameBuffer::depth_only(&self.system.display, &self.shadow_texture).unwrap();
-        shadow_target.clear_color(1.0, 1.0, 1.0, 1.0);
-        shadow_target.clear_depth(1.0);
+    /// Uploads any meshes registered since the last frame.
+    fn upload_meshes(&mut self, registry: &MeshRegistry) {
+        for index in self.meshes.len()..registry.len() {
+            let asset = registry.asset(Mesh(index));
+            self.meshes.push(GpuMesh {
+                vertices: glium::VertexBuffer::new(&self.system.display, &asset.vertices).unwrap().into(),
+                indices: IndexBuffer::new(&self.system.display, glium::index::PrimitiveType::TrianglesList, &asset.indices).unwrap(),
+            });
+        }
+    }
 
-        let mut box_transforms: Vec<Mat4> = Vec::new();
-        let mut sphere_transforms: Vec<Mat4> = Vec::new();
-        let mut plane_transforms: Vec<Mat4> = Vec::new();
+    /// Uploads any textures registered since the last frame, with mipmaps. They're kept as they're
+    /// stored rather than as sRGB, like every other colour, since the framebuffer isn't sRGB either.
+    fn upload_textures(&mut self, registry: &TextureRegistry) {
+        for index in self.textures.len()..registry.len() {
+            let asset = registry.asset(Texture(index));
+            let image = glium::texture::RawImage2d::from_raw_rgba_reversed(&asset.pixels, asset.dimensions);
+            let texture = Texture2d::with_mipmaps(&self.system.display, image, MipmapsOption::AutoGeneratedMipmaps).unwrap();
+            self.textures.push(texture);
+        }
+    }
 
-        for (transform, mesh_renderer) in (transforms, mesh_renderers).join() {
-            match mesh_renderer.0 {
-                Mesh::Cube => box_transforms.push(transform.0),
-                Mesh::Sphere => sphere_transforms.push(transform.0),
-                Mesh::Plane => plane_transforms.push(transform.0),
+    /// Every visible entity, bucketed by mesh and texture.
+    fn batch_instances<'b>(&self, transforms: &ReadStorage<'b, Transform>, previous_transforms: &ReadStorage<'b, PreviousTransform>, mesh_renderers: &ReadStorage<'b, MeshRenderer>, materials: &ReadStorage<'b, Material>, alpha: f32) -> HashMap<(Mesh, Option<Texture>), Vec<Instance>> {
+        let mut batches: HashMap<(Mesh, Option<Texture>), Vec<Instance>> = HashMap::new();
+        let default_material = Material::default();
+
+        for (transform, previous, mesh_renderer, material) in (transforms, previous_transforms.maybe(), mesh_renderers, materials.maybe()).join() {
+            let material = material.unwrap_or(&default_material);
+            let texture = material.texture.filter(|texture| texture.0 < self.textures.len());
+            if mesh_renderer.0 .0 < self.meshes.len() {
+                let batch = batches.entry((mesh_renderer.0, texture)).or_default();
+                batch.push(Instance {
+                    model: interpolated_model(transform, previous, alpha).to_cols_array_2d(),
+                    color: material.color.into(),
+                    roughness: material.roughness,
+                    emissive: material.emissive,
+                });
             }
         }
 
-        let box_transform_buffer = self.make_instance_buffer(&box_transforms);
-        let sphere_transform_buffer = self.make_instance_buffer(&sphere_transforms);
+        return batches;
+    }
 
-        self.draw_instanced_mesh_shadow(&mut shadow_target, &self.cube, &box_transform_buffer);
-        self.draw_instanced_mesh_shadow(&mut shadow_target, &self.sphere, &sphere_transform_buffer);
+    fn draw_mesh_shadows(&self, batches: &HashMap<(Mesh, Option<Texture>), Vec<Instance>>, registry: &MeshRegistry) {
+        let mut shadow_target = glium::framebuffer::SimpleFrameBuffer::depth_only(&self.system.display, &self.shadow_texture).unwrap();
+        shadow_target.clear_color(1.0, 1.0, 1.0, 1.0);
+        shadow_target.clear_depth(1.0);
+
+        for ((mesh, _), batch) in batches {
+            if !registry.asset(*mesh).casts_shadows {
+                continue;
+            }
+            let instance_buffer = self.make_instance_buffer(batch);
+            self.draw_instanced_mesh_shadow(&mut shadow_target, &self.meshes[mesh.0], &instance_buffer);
+        }
     }
 
-    fn draw_instanced_mesh_shadow(&self, shadow_target: &mut SimpleFrameBuffer, mesh: &VertexBufferAny, instances: &VertexBufferAny) {
+    fn draw_instanced_mesh_shadow(&self, shadow_target: &mut SimpleFrameBuffer, mesh: &GpuMesh, instances: &VertexBufferAny) {
         let uniforms = uniform! {
             projection: self.shadow_projection.to_cols_array_2d(),
             view: self.shadow_view.to_cols_array_2d(),
         };
 
         shadow_target.draw(
-            (mesh, instances.per_instance().unwrap()),
-            &glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList),
+            (&mesh.vertices, instances.per_instance().unwrap()),
+            &mesh.indices,
             &self.instanced_shadow_program,
             &uniforms,
             &self.shadow_draw_params,
         ).unwrap();
     }
 
-    fn draw_meshes<'b>(&self, target: &mut glium::Frame, transforms: &ReadStorage<'b, Transform>, mesh_renderers: &ReadStorage<'b, MeshRenderer>) {
-        let mut cube_transforms: Vec<Mat4> = Vec::new();
-        let mut sphere_transforms: Vec<Mat4> = Vec::new();
-        let mut plane_transforms: Vec<Mat4> = Vec::new();
-
-        for (transform, mesh_renderer) in (transforms, mesh_renderers).join() {
-            match mesh_renderer.0 {
-                Mesh::Cube => cube_transforms.push(transform.0),
-                Mesh::Sphere => sphere_transforms.push(transform.0),
-                Mesh::Plane => plane_transforms.push(transform.0),
-            }
+    fn draw_meshes(&self, target: &mut glium::Frame, batches: &HashMap<(Mesh, Option<Texture>), Vec<Instance>>) {
+        for ((mesh, texture), batch) in batches {
+            let mesh = &self.meshes[mesh.0];
+            let texture = texture.map(|texture| &self.textures[texture.0]);
+            let instance_buffer = self.make_instance_buffer(batch);
+            self.draw_instanced_mesh(target, mesh, texture, &instance_buffer);
+            self.draw_instanced_mesh_outline(target, mesh, &instance_buffer);
         }
-
-        let cube_transform_buffer = self.make_instance_buffer(&cube_transforms);
-        let sphere_transform_buffer = self.make_instance_buffer(&sphere_transforms);
-        let plane_transform_buffer = self.make_instance_buffer(&plane_transforms);
-
-        self.draw_instanced_mesh(target, &self.cube, &cube_transform_buffer);
-        self.draw_instanced_mesh(target, &self.sphere, &sphere_transform_buffer);
-        self.draw_instanced_mesh(target, &self.plane, &plane_transform_buffer);
-
-        self.draw_instanced_mesh_outline(target, &self.cube, &cube_transform_buffer);
-        self.draw_instanced_mesh_outline(target, &self.sphere, &sphere_transform_buffer);
-        self.draw_instanced_mesh_outline(target, &self.plane, &plane_transform_buffer);
     }
 
-    fn draw_instanced_mesh(&self, target: &mut glium::Frame, mesh: &VertexBufferAny, instances: &VertexBufferAny) {
+    fn draw_instanced_mesh(&self, target: &mut glium::Frame, mesh: &GpuMesh, texture: Option<&Texture2d>, instances: &VertexBufferAny) {
         let aspect_ratio = {
             let (width, height) = self.system.display.get_framebuffer_dimensions();
             width as f32 / height as f32
@@ -232,7 +284,7 @@ impl<'a> RenderingSystem<'a> {
             light_loc: [self.light_loc.x(), self.light_loc.y(), self.light_loc.z()],
             projection: projection.to_cols_array_2d(),
             view: view.to_cols_array_2d(),
-            paint: [0.0, 1.0, 0.0f32],
+            camera_position: [self.camera.position.x(), self.camera.position.y(), self.camera.position.z()],
             depth_bias_mvp: bias_depth_mvp.to_cols_array_2d(),
             shadow_map: glium::uniforms::Sampler::new(&self.shadow_texture)
                 .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest)
@@ -240,16 +292,34 @@ impl<'a> RenderingSystem<'a> {
                 .depth_texture_comparison(Some(glium::uniforms::DepthTextureComparison::LessOrEqual)),
         };
 
-        target.draw(
-            (mesh, instances.per_instance().unwrap()),
-            &glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList),
-            &self.instanced_shadow_diffuse_program,
-            &fill_uniforms,
-            &draw_params,
-        ).unwrap();
+        match texture {
+            Some(texture) => {
+                let diffuse_texture = glium::uniforms::Sampler::new(texture)
+                    .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest)
+                    .minify_filter(glium::uniforms::MinifySamplerFilter::LinearMipmapLinear)
+                    .wrap_function(glium::uniforms::SamplerWrapFunction::Repeat);
+
+                target.draw(
+                    (&mesh.vertices, instances.per_instance().unwrap()),
+                    &mesh.indices,
+                    &self.textured_shadow_diffuse_program,
+                    &fill_uniforms.add("diffuse_texture", diffuse_texture),
+                    &draw_params,
+                ).unwrap();
+            }
+            None => {
+                target.draw(
+                    (&mesh.vertices, instances.per_instance().unwrap()),
+                    &mesh.indices,
+                    &self.instanced_shadow_diffuse_program,
+                    &fill_uniforms,
+                    &draw_params,
+                ).unwrap();
+            }
+        }
     }
 
-    fn draw_instanced_mesh_outline(&self, target: &mut glium::Frame, mesh: &VertexBufferAny, instances: &VertexBufferAny) {
+    fn draw_instanced_mesh_outline(&self, target: &mut glium::Frame, mesh: &GpuMesh, instances: &VertexBufferAny) {
         let aspect_ratio = {
             let (width, height) = self.system.display.get_framebuffer_dimensions();
             width as f32 / height as f32
@@ -274,8 +344,8 @@ impl<'a> RenderingSystem<'a> {
 
         draw_params.polygon_mode = PolygonMode::Line;
         target.draw(
-            (mesh, instances.per_instance().unwrap()),
-            &glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList),
+            (&mesh.vertices, instances.per_instance().unwrap()),
+            &mesh.indices,
             &self.instanced_diffuse_program,
             &line_uniforms,
             &draw_params,
@@ -283,20 +353,50 @@ impl<'a> RenderingSystem<'a> {
         draw_params.polygon_mode = PolygonMode::Fill;
     }
 
-    fn make_instance_buffer(&self, instances: &Vec<Mat4>) -> VertexBufferAny{
-        #[derive(Copy, Clone)]
-        struct Instance {
-            model: [[f32; 4]; 4]
+    fn draw_debug_lines(&self, target: &mut glium::Frame, lines: &[DebugLine]) {
+        if lines.is_empty() {
+            return;
         }
 
-        implement_vertex!(Instance, model);
+        let aspect_ratio = {
+            let (width, height) = self.system.display.get_framebuffer_dimensions();
+            width as f32 / height as f32
+        };
+        let projection = Mat4::perspective_rh_gl(3.3141 / 4.0, aspect_ratio, 0.1, 1000.0);
+        let view = self.camera.transform();
 
-        let data = instances.iter().map(|model| {
-            Instance {
-                model: model.to_cols_array_2d()
-            }
+        let vertices = lines.iter().flat_map(|line| {
+            let color = [line.color.x(), line.color.y(), line.color.z()];
+            vec![
+                LineVertex { position: [line.start.x(), line.start.y(), line.start.z()], color },
+                LineVertex { position: [line.end.x(), line.end.y(), line.end.z()], color },
+            ]
         }).collect::<Vec<_>>();
-        glium::vertex::VertexBuffer::dynamic(&self.system.display, &data).unwrap().into()
+        let vertex_buffer = glium::VertexBuffer::new(&self.system.display, &vertices).unwrap();
+
+        let uniforms = uniform! {
+            projection: projection.to_cols_array_2d(),
+            view: view.to_cols_array_2d(),
+        };
+
+        let mut draw_params: glium::draw_parameters::DrawParameters = Default::default();
+        draw_params.depth = glium::Depth {
+            test: glium::draw_parameters::DepthTest::IfLessOrEqual,
+            write: true,
+            ..Default::default()
+        };
+
+        target.draw(
+            &vertex_buffer,
+            &glium::index::NoIndices(glium::index::PrimitiveType::LinesList),
+            &self.lines_program,
+            &uniforms,
+            &draw_params,
+        ).unwrap();
+    }
+
+    fn make_instance_buffer(&self, instances: &[Instance]) -> VertexBufferAny {
+        glium::vertex::VertexBuffer::dynamic(&self.system.display, instances).unwrap().into()
     }
 
     fn draw_debug_shadow_map(&self, target: &mut glium::Frame) {
@@ -332,12 +432,44 @@ impl<'a> RenderingSystem<'a> {
     }
 }
 
+/// Blends between the pose before and after the latest physics step.
+fn interpolated_model(transform: &Transform, previous: Option<&PreviousTransform>, alpha: f32) -> Mat4 {
+    match previous {
+        Some(previous) => {
+            let (from_scale, from_rotation, from_translation) = previous.0.to_scale_rotation_translation();
+            let (to_scale, to_rotation, to_translation) = transform.0.to_scale_rotation_translation();
+            Mat4::from_scale_rotation_translation(
+                from_scale.lerp(to_scale, alpha),
+                from_rotation.slerp(to_rotation, alpha),
+                from_translation.lerp(to_translation, alpha),
+            )
+        }
+        None => transform.0,
+    }
+}
+
 impl<'a> System<'a> for RenderingSystem<'_> {
     type SystemData = (Read<'a, DeltaTime>,
+                       Read<'a, InterpolationAlpha>,
                        ReadStorage<'a, Transform>,
-                       ReadStorage<'a, MeshRenderer>);
-
-    fn run(&mut self, (dt, transforms, mesh_renderers): Self::SystemData) {
+                       ReadStorage<'a, PreviousTransform>,
+                       ReadStorage<'a, MeshRenderer>,
+                       ReadExpect<'a, PhysicsQueries>,
+                       Write<'a, PlayerInput>,
+                       ReadStorage<'a, CharacterController>,
+                       Write<'a, PhysicsConfig>,
+                       ReadStorage<'a, Rigidbody>,
+                       ReadStorage<'a, Sleeping>,
+                       Write<'a, DebugLines>,
+                       ReadExpect<'a, MeshRegistry>,
+                       ReadStorage<'a, Material>,
+                       ReadExpect<'a, TextureRegistry>,
+                       ReadStorage<'a, Velocity>,
+                       Read<'a, EventChannel<ContactEvent>>,
+                       Read<'a, EventChannel<TriggerEvent>>,
+                       Write<'a, SnapshotRequest>);
+
+    fn run(&mut self, (dt, alpha, transforms, previous_transforms, mesh_renderers, queries, mut input, characters, mut physics_config, rigidbodies, sleeping, mut debug_lines, meshes, materials, textures, velocities, contact_events, trigger_events, mut snapshot_request): Self::SystemData) {
         let sw = Stopwatch::start_new();
 
         {
@@ -358,18 +490,50 @@ impl<'a> System<'a> for RenderingSystem<'_> {
             });
         }
 
-        self.camera.update_from_io(self.system.imgui.io());
+        let io = self.system.imgui.io();
+        self.camera.update_from_io(io);
+
+        *input = PlayerInput::default();
+        if self.follow_character {
+            // Walk relative to the way the camera faces, ignoring its pitch.
+            let forward = self.camera.forward() * Vec3::new(1.0, 0.0, 1.0);
+            let forward = if forward.length() > 0.0 { forward.normalize() } else { -Vec3::unit_z() };
+            let right = forward.cross(Vec3::unit_y());
+            let movement = crate::camera::movement_input(io);
+            input.movement = right * movement.x() + forward * movement.y();
+            input.jump = io.keys_down[io[imgui::Key::Space] as usize];
+
+            let character = (&transforms, previous_transforms.maybe(), &characters).join().next();
+            if let Some((transform, previous, _)) = character {
+                self.camera.follow(interpolated_model(transform, previous, alpha.0).w_axis().truncate());
+            }
+        }
 
         let mut target = self.system.display.draw();
         target.clear_color_and_depth((0.01, 0.01, 0.01, 1.0), 1.0);
 
-        self.draw_mesh_shadows(&transforms, &mesh_renderers);
-        self.draw_meshes(&mut target, &transforms, &mesh_renderers);
+        self.upload_meshes(&meshes);
+        self.upload_textures(&textures);
+        let batches = self.batch_instances(&transforms, &previous_transforms, &mesh_renderers, &materials, alpha.0);
+        self.draw_mesh_shadows(&batches, &meshes);
+        self.draw_meshes(&mut target, &batches);
+        self.draw_debug_lines(&mut target, &debug_lines.lines);
 
         let mut ui = self.system.imgui.frame();
 
         {
             let camera = &self.camera;
+            let follow_character = &mut self.follow_character;
+            let looking_at = queries.raycast(camera.position, camera.forward(), 1000.0, !0);
+            let looking_at_velocity = looking_at.as_ref().and_then(|hit| velocities.get(hit.entity));
+            // How far a ball or crate thrown from the camera would get, and what's crowded around where it's looking.
+            let ball_sweep = queries.sweep_sphere(0.5, camera.position, camera.forward(), 1000.0, !0);
+            let crate_sweep = queries.sweep_box(Vec3::splat(0.5), Mat4::from_translation(camera.position), camera.forward(), 1000.0, !0);
+            let nearby = looking_at.as_ref().map(|hit| {
+                let within_sphere = queries.overlap_sphere(5.0, hit.position, !0).len();
+                let within_box = queries.overlap_box(Vec3::splat(5.0), Mat4::from_translation(hit.position), !0).len();
+                (within_sphere, within_box)
+            });
 
             Window::new(im_str!("Hello world"))
                 .size([300.0, 100.0], Condition::FirstUseEver)
@@ -393,6 +557,106 @@ impl<'a> System<'a> for RenderingSystem<'_> {
                         "Azimuth Pitch: ({:.1},{:.1})",
                         camera.azimuth, camera.pitch,
                     ));
+
+                    ui.checkbox(im_str!("Follow character"), follow_character);
+
+                    match &looking_at {
+                        Some(hit) => {
+                            ui.text(format!("Looking at: {:?} ({:.1}m)", hit.entity, hit.distance));
+                            ui.text(format!("  at ({:.1},{:.1},{:.1}) facing ({:.1},{:.1},{:.1})",
+                                hit.position.x(), hit.position.y(), hit.position.z(),
+                                hit.normal.x(), hit.normal.y(), hit.normal.z()));
+                        }
+                        None => ui.text(im_str!("Looking at: nothing")),
+                    }
+                    if let Some((within_sphere, within_box)) = nearby {
+                        ui.text(format!("  {} things within 5m, {} in a 10m box", within_sphere, within_box));
+                    }
+                    let thrown = |hit: &Option<QueryHit>| hit.as_ref().map_or("nothing".to_string(), |hit| format!("{:?} after {:.1}m", hit.entity, hit.distance));
+                    ui.text(format!("A thrown ball hits {}", thrown(&ball_sweep)));
+                    ui.text(format!("A thrown crate hits {}", thrown(&crate_sweep)));
+                    if let Some(velocity) = looking_at_velocity {
+                        ui.text(format!("Moving at {:.1}m/s, spinning at {:.1}rad/s", velocity.linear.length(), velocity.angular.length()));
+                    }
+                });
+
+            let bodies = (&rigidbodies).join().count();
+            let asleep = (&rigidbodies, &sleeping).join().count();
+
+            // Only entities with `ContactReports` come with points; ones that are still apart don't count.
+            let mut contacts_begun = 0;
+            for event in contact_events.read(self.contact_events.as_mut().expect("RenderingSystem::setup wasn't called")) {
+                if event.kind == ContactKind::Begin {
+                    contacts_begun += 1;
+                }
+                for point in event.points.iter().filter(|point| point.separation <= 0.0) {
+                    let harder = match self.hardest_impact {
+                        Some((hardest, _)) => point.impulse.length() > hardest.impulse.length(),
+                        None => true,
+                    };
+                    if harder {
+                        self.hardest_impact = Some((*point, event.entities));
+                    }
+                }
+            }
+            let hardest_impact = self.hardest_impact;
+
+            for event in trigger_events.read(self.trigger_events.as_mut().expect("RenderingSystem::setup wasn't called")) {
+                let occupants = self.trigger_occupants.entry(event.trigger).or_default();
+                match event.kind {
+                    TriggerKind::Enter => occupants.insert(event.other),
+                    TriggerKind::Exit => occupants.remove(&event.other),
+                };
+            }
+            let trigger_occupants = &self.trigger_occupants;
+
+            let config = &mut *physics_config;
+            let debug_enabled = &mut debug_lines.enabled;
+            let snapshot_request = &mut *snapshot_request;
+            Window::new(im_str!("Physics"))
+                .size([300.0, 220.0], Condition::FirstUseEver)
+                .position([20.0, 140.0], Condition::FirstUseEver)
+                .build(&ui, || {
+                    let mut gravity: [f32; 3] = config.gravity.into();
+                    if ui.drag_float3(im_str!("Gravity"), &mut gravity).speed(0.1).build() {
+                        config.gravity = gravity.into();
+                    }
+                    Slider::new(im_str!("Position iterations"), 1..=32).build(&ui, &mut config.position_iterations);
+                    Slider::new(im_str!("Velocity iterations"), 1..=32).build(&ui, &mut config.velocity_iterations);
+                    Slider::new(im_str!("Bounce threshold"), 0.0..=10.0).build(&ui, &mut config.bounce_threshold);
+                    Slider::new(im_str!("Sleep threshold"), 0.0..=0.1).build(&ui, &mut config.sleep_threshold);
+                    ui.text(format!("Threads: {} (applied on startup)", config.threads));
+
+                    ui.separator();
+                    ui.text(format!("Bodies: {} ({} asleep)", bodies, asleep));
+                    ui.text(format!("Contacts begun: {}", contacts_begun));
+                    if let Some((point, (first, second))) = hardest_impact {
+                        ui.text(format!("Hardest impact: {:.1}Ns, {:?} against {:?}", point.impulse.length(), first, second));
+                        ui.text(format!("  at ({:.1},{:.1},{:.1}) along ({:.1},{:.1},{:.1})",
+                            point.position.x(), point.position.y(), point.position.z(),
+                            point.normal.x(), point.normal.y(), point.normal.z()));
+                    }
+                    for (trigger, occupants) in trigger_occupants {
+                        ui.text(format!("Inside {:?}: {}", trigger, occupants.len()));
+                    }
+                    ui.checkbox(im_str!("Show colliders and contacts"), debug_enabled);
+                    snapshot_request.save = ui.small_button(im_str!("Save snapshot"));
+                    ui.same_line(0.0);
+                    snapshot_request.restore = ui.small_button(im_str!("Restore snapshot"));
+                });
+
+            Window::new(im_str!("Assets"))
+                .size([300.0, 160.0], Condition::FirstUseEver)
+                .position([20.0, 380.0], Condition::FirstUseEver)
+                .build(&ui, || {
+                    for index in 0..meshes.len() {
+                        let asset = meshes.asset(Mesh(index));
+                        ui.text(format!("{}: {} vertices, {} indices", asset.name, asset.vertices.len(), asset.indices.len()));
+                    }
+                    for index in 0..textures.len() {
+                        let asset = textures.asset(Texture(index));
+                        ui.text(format!("{}: {}x{} texture", asset.name, asset.dimensions.0, asset.dimensions.1));
+                    }
                 });
         }
 
@@ -407,6 +671,12 @@ impl<'a> System<'a> for RenderingSystem<'_> {
 
         println!("Rendering took {}ms", sw.elapsed_ms());
     }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.contact_events = Some(world.fetch_mut::<EventChannel<ContactEvent>>().register_reader());
+        self.trigger_events = Some(world.fetch_mut::<EventChannel<TriggerEvent>>().register_reader());
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -422,4 +692,27 @@ impl DebugVertex {
             tex_coords,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Per-instance attributes of an instanced draw.
+#[derive(Clone, Copy, Debug)]
+struct Instance {
+    model: [[f32; 4]; 4],
+    color: [f32; 3],
+    roughness: f32,
+    emissive: f32,
+}
+implement_vertex!(Instance, model, color, roughness, emissive);
+
+/// A registered mesh once uploaded.
+struct GpuMesh {
+    vertices: VertexBufferAny,
+    indices: IndexBuffer<u32>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct LineVertex {
+    position: [f32; 3],
+    color: [f32; 3],
+}
+implement_vertex!(LineVertex, position, color);
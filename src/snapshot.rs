@@ -0,0 +1,92 @@
+use std::convert::TryInto;
+use std::io::{Error, ErrorKind};
+
+use glam::*;
+use specs::world::Index;
+
+/// The state of one simulated body, keyed by the entity owning it. The pose is kept exactly as PhysX
+/// has it rather than as a matrix, which wouldn't round trip.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BodySnapshot {
+    pub entity: Index,
+    /// The entity's generation, so a new entity reusing the index isn't mistaken for it.
+    pub generation: i32,
+    pub rotation: Quat,
+    pub position: Vec3,
+    pub linear_velocity: Vec3,
+    pub angular_velocity: Vec3,
+    /// How long PhysX will keep the body awake without it moving.
+    pub wake_counter: f32,
+    pub sleeping: bool,
+}
+
+/// Every dynamic and kinematic body in the scene, taken by `PhysicsSystem::snapshot`. Contact state
+/// isn't included.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PhysicsSnapshot {
+    pub bodies: Vec<BodySnapshot>,
+}
+
+/// Bytes each body takes up: its index and generation, 14 floats and the sleep flag.
+const BODY_SIZE: usize = 8 + 14 * 4 + 1;
+
+impl PhysicsSnapshot {
+    /// A little-endian encoding that `from_bytes` reads back exactly.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4 + self.bodies.len() * BODY_SIZE);
+        bytes.extend_from_slice(&(self.bodies.len() as u32).to_le_bytes());
+
+        for body in &self.bodies {
+            bytes.extend_from_slice(&body.entity.to_le_bytes());
+            bytes.extend_from_slice(&body.generation.to_le_bytes());
+            let rotation = [body.rotation.x(), body.rotation.y(), body.rotation.z(), body.rotation.w()];
+            let floats = rotation.iter()
+                .chain(body.position.as_ref().iter())
+                .chain(body.linear_velocity.as_ref().iter())
+                .chain(body.angular_velocity.as_ref().iter())
+                .chain(std::iter::once(&body.wake_counter))
+                .copied()
+                .collect::<Vec<f32>>();
+            for float in floats {
+                bytes.extend_from_slice(&float.to_le_bytes());
+            }
+            bytes.push(body.sleeping as u8);
+        }
+
+        return bytes;
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> std::io::Result<PhysicsSnapshot> {
+        let invalid = || Error::new(ErrorKind::InvalidData, "truncated physics snapshot");
+        let word = |offset: usize| -> std::io::Result<[u8; 4]> {
+            bytes.get(offset..offset + 4).ok_or_else(invalid)?.try_into().map_err(|_| invalid())
+        };
+
+        let count = u32::from_le_bytes(word(0)?) as usize;
+        if bytes.len() != 4 + count * BODY_SIZE {
+            return Err(invalid());
+        }
+
+        let mut bodies = Vec::with_capacity(count);
+        for i in 0..count {
+            let start = 4 + i * BODY_SIZE;
+            let mut floats = [0.0f32; 14];
+            for (j, float) in floats.iter_mut().enumerate() {
+                *float = f32::from_le_bytes(word(start + 8 + j * 4)?);
+            }
+
+            bodies.push(BodySnapshot {
+                entity: u32::from_le_bytes(word(start)?),
+                generation: i32::from_le_bytes(word(start + 4)?),
+                rotation: Quat::from_xyzw(floats[0], floats[1], floats[2], floats[3]),
+                position: Vec3::new(floats[4], floats[5], floats[6]),
+                linear_velocity: Vec3::new(floats[7], floats[8], floats[9]),
+                angular_velocity: Vec3::new(floats[10], floats[11], floats[12]),
+                wake_counter: floats[13],
+                sleeping: bytes[start + BODY_SIZE - 1] != 0,
+            });
+        }
+
+        return Ok(PhysicsSnapshot { bodies });
+    }
+}
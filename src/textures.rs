@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+
+use crate::common::Texture;
+
+/// A decoded image behind a `Texture` handle, as RGBA8 rows from the top down.
+pub struct TextureAsset {
+    pub name: String,
+    pub pixels: Vec<u8>,
+    pub dimensions: (u32, u32),
+}
+
+/// Every image `Material`s can be textured with, by name. Like the `MeshRegistry`, textures are
+/// only ever added and the renderer uploads new ones as they appear.
+#[derive(Default)]
+pub struct TextureRegistry {
+    textures: Vec<TextureAsset>,
+    names: HashMap<String, Texture>,
+}
+
+impl TextureRegistry {
+    /// Decodes a PNG or JPEG and registers it under `name`, or returns the texture already registered there.
+    pub fn insert(&mut self, name: &str, data: &[u8]) -> image::ImageResult<Texture> {
+        if let Some(texture) = self.names.get(name) {
+            return Ok(*texture);
+        }
+
+        let image = image::load_from_memory(data)?.to_rgba();
+        let dimensions = image.dimensions();
+
+        let texture = Texture(self.textures.len());
+        self.textures.push(TextureAsset { name: name.to_string(), pixels: image.into_raw(), dimensions });
+        self.names.insert(name.to_string(), texture);
+        return Ok(texture);
+    }
+
+    pub fn asset(&self, texture: Texture) -> &TextureAsset {
+        &self.textures[texture.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+}